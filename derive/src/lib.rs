@@ -1,5 +1,5 @@
 #![allow(
-    clippy::blocks_in_if_conditions,
+    clippy::blocks_in_conditions,
     clippy::cast_lossless,
     clippy::cast_possible_truncation,
     clippy::manual_find,
@@ -19,15 +19,48 @@
 extern crate proc_macro;
 
 mod parse;
+mod test_attr;
 
 use proc_macro::TokenStream;
 use quote::ToTokens;
+use syn::parse::{ParseStream, Parser};
 use syn::parse_macro_input;
 
+/// Make a function, or the methods of an impl or trait, mockable.
+///
+/// With `#[mock(eager)]`, async functions look up their mock when called, rather than when first polled.
 #[proc_macro_attribute]
-pub fn mock(_: TokenStream, token_stream: TokenStream) -> TokenStream {
-    // token_stream.
-    let input = parse_macro_input!(token_stream as parse::Item);
+pub fn mock(args: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as parse::MockArgs);
+    let parser = |input: ParseStream| parse::Item::parse(input, &args);
 
-    input.to_token_stream().into()
+    parser
+        .parse(token_stream)
+        .map(|input| input.to_token_stream())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// Run a test with its own mock store.
+///
+/// The store is cleared before the test, so mocks, calls and expectations left by earlier tests on the thread don't leak in.
+/// After the test body, the test fails if expectations are unmet,
+/// or if any `mock_once`/`mock_repeat(Some(n), ..)` mock was not used up,
+/// with a summary of each of them.
+///
+/// With `#[mockem::test(strict)]`, the test runs in strict mode, as with `mockem::strict`.
+/// With `#[mockem::test(shared)]`, it runs in a `mockem::shared_scope`, so it can use `mock_shared`,
+/// or for async tests with the `tokio` feature, in a task scope from `mockem::tokio::scope`;
+/// other tests don't see shared mocks from other threads. Flags can be combined, e.g. `#[mockem::test(strict, shared)]`.
+///
+/// `#[test]` is added unless the function already has a test attribute.
+/// Async tests need one with a runtime, placed after this one, e.g. `#[tokio::test]`.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, token_stream: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as test_attr::TestArgs);
+    let input = parse_macro_input!(token_stream as syn::ItemFn);
+
+    test_attr::expand(args, input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
 }
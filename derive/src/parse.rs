@@ -1,10 +1,40 @@
 use quote::{quote, ToTokens};
 use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
 use syn::{
-    Attribute, Block, GenericParam, ImplItem, ItemFn, ItemImpl, ItemTrait, Token, TraitItem,
-    Visibility,
+    parse_quote, Attribute, Block, Error, Expr, FnArg, GenericArgument, GenericParam, Generics,
+    Ident, ImplItem, ItemFn, ItemImpl, ItemTrait, Lifetime, LifetimeParam,
+    ParenthesizedGenericArguments, PathArguments, ReturnType, Signature, Stmt, Token, TraitItem,
+    Type, TypeBareFn, TypeParamBound, TypeReference, Visibility,
 };
 
+/// Arguments of `#[mock]`.
+#[derive(Default)]
+pub struct MockArgs {
+    eager: bool,
+}
+
+impl Parse for MockArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let flags = Punctuated::<Ident, Token![,]>::parse_terminated(input)?;
+        let mut args = Self::default();
+
+        for flag in flags {
+            if flag == "eager" {
+                args.eager = true;
+            } else {
+                return Err(Error::new_spanned(
+                    flag,
+                    "unknown argument, expected `eager`",
+                ));
+            }
+        }
+
+        Ok(args)
+    }
+}
+
 pub enum Item {
     Fn(ItemFn),
     Impl(ItemImpl),
@@ -21,8 +51,8 @@ impl ToTokens for Item {
     }
 }
 
-impl Parse for Item {
-    fn parse(input: ParseStream) -> Result<Self> {
+impl Item {
+    pub fn parse(input: ParseStream, args: &MockArgs) -> Result<Self> {
         let attrs = input.call(Attribute::parse_outer)?;
 
         let mut lookahead = input.lookahead1();
@@ -43,39 +73,28 @@ impl Parse for Item {
         };
 
         if lookahead.peek(Token![impl]) {
-            inject_impl(input, attrs)
+            inject_impl(input, attrs, args)
         } else if lookahead.peek(Token![trait]) {
-            inject_trait(input, pub_token, attrs)
+            inject_trait(input, pub_token, attrs, args)
         } else {
-            inject_fn(input, pub_token, attrs)
+            inject_fn(input, pub_token, attrs, args)
         }
     }
 }
 
-fn inject_impl(input: ParseStream, attrs: Vec<Attribute>) -> Result<Item> {
+fn inject_impl(input: ParseStream, attrs: Vec<Attribute>, args: &MockArgs) -> Result<Item> {
+    check_eager(args, &attrs)?;
+
     let mut item: ItemImpl = input.parse()?;
     item.attrs = attrs;
     let trait_name = item.trait_.clone();
+    let item_generics = item.generics.clone();
+    let impl_lifetimes = lifetimes(&item.generics);
 
     for item in item.items.iter_mut() {
         if let ImplItem::Fn(method) = item {
             let name = method.sig.ident.clone();
-            let generics = method
-                .sig
-                .generics
-                .params
-                .iter()
-                .map(|p| match p {
-                    GenericParam::Lifetime(lt) => lt.lifetime.ident.clone(),
-                    GenericParam::Type(ty) => ty.ident.clone(),
-                    GenericParam::Const(c) => c.ident.clone(),
-                })
-                .collect::<Vec<_>>();
-            let generics = if generics.is_empty() {
-                quote!()
-            } else {
-                quote!(::<#(#generics),*>)
-            };
+            let generics = turbofish(&method.sig);
 
             let self_type = if let Some((_, path, _)) = &trait_name {
                 quote!(<Self as #path>)
@@ -83,35 +102,26 @@ fn inject_impl(input: ParseStream, attrs: Vec<Attribute>) -> Result<Item> {
                 quote!(Self)
             };
 
-            let args = method.sig.inputs.iter().map(|a| {
-                if let syn::FnArg::Typed(pat) = a {
-                    let pat = &pat.pat;
-                    quote!(#pat)
-                } else {
-                    quote!(self)
-                }
-            });
-
-            let ret = if let syn::ReturnType::Type(_, ty) = &method.sig.output {
-                quote!(#ty)
+            let eager = if !args.eager {
+                Eager::No
+            } else if trait_name.is_some() {
+                Eager::InTrait
             } else {
-                quote!(())
+                Eager::Capturing(impl_lifetimes.clone())
             };
 
-            let mut stms = syn::parse2::<Block>(quote!({
-                {
-                    use mockem::CallMock;
-
-                    if #self_type :: #name #generics .mock_exists(core::marker::PhantomData::<#ret>) {
-                        return #self_type :: #name #generics .call_mock((#(#args,)*));
-                    }
-                }
-            }))?
-            .stmts;
-
-            std::mem::swap(&mut method.block.stmts, &mut stms);
+            let in_trait = trait_name.as_ref().map(|(_, path, _)| InTrait {
+                path: quote!(#path),
+                generics: item_generics.clone(),
+            });
 
-            method.block.stmts.extend(stms);
+            inject(
+                quote!(#self_type :: #name #generics),
+                in_trait,
+                &mut method.sig,
+                &mut method.block,
+                eager,
+            )?;
         }
     }
 
@@ -122,7 +132,10 @@ fn inject_trait(
     input: ParseStream,
     pub_token: Option<Token![pub]>,
     attrs: Vec<Attribute>,
+    args: &MockArgs,
 ) -> Result<Item> {
+    check_eager(args, &attrs)?;
+
     let mut item: ItemTrait = input.parse()?;
     item.attrs = attrs;
 
@@ -131,58 +144,33 @@ fn inject_trait(
     }
 
     let trait_name = item.ident.clone();
+    let (_, trait_generics, _) = item.generics.split_for_impl();
+    let trait_path = quote!(#trait_name #trait_generics);
+    let item_generics = item.generics.clone();
 
     for item in item.items.iter_mut() {
         if let TraitItem::Fn(method) = item {
             if let Some(block) = method.default.as_mut() {
                 let name = method.sig.ident.clone();
-                let generics = method
-                    .sig
-                    .generics
-                    .params
-                    .iter()
-                    .map(|p| match p {
-                        GenericParam::Lifetime(lt) => lt.lifetime.ident.clone(),
-                        GenericParam::Type(ty) => ty.ident.clone(),
-                        GenericParam::Const(c) => c.ident.clone(),
-                    })
-                    .collect::<Vec<_>>();
-
-                let generics = if generics.is_empty() {
-                    quote!()
-                } else {
-                    quote!(::<#(#generics),*>)
-                };
-
-                let args = method.sig.inputs.iter().map(|a| {
-                    if let syn::FnArg::Typed(pat) = a {
-                        let pat = &pat.pat;
-                        quote!(#pat)
-                    } else {
-                        quote!(self)
-                    }
-                });
+                let generics = turbofish(&method.sig);
 
-                let ret = if let syn::ReturnType::Type(_, ty) = &method.sig.output {
-                    quote!(#ty)
+                let eager = if args.eager {
+                    Eager::InTrait
                 } else {
-                    quote!(())
+                    Eager::No
+                };
+                let in_trait = InTrait {
+                    path: trait_path.clone(),
+                    generics: item_generics.clone(),
                 };
 
-                let mut stms = syn::parse2::<Block>(quote!({
-                    {
-                        use mockem::CallMock;
-
-                        if <Self as #trait_name> :: #name #generics .mock_exists(core::marker::PhantomData::<#ret>) {
-                            return <Self as #trait_name> :: #name #generics .call_mock((#(#args,)*));
-                        }
-                    }
-                }))?
-                .stmts;
-
-                std::mem::swap(&mut block.stmts, &mut stms);
-
-                block.stmts.extend(stms);
+                inject(
+                    quote!(<Self as #trait_name> :: #name #generics),
+                    Some(in_trait),
+                    &mut method.sig,
+                    block,
+                    eager,
+                )?;
             }
         }
     }
@@ -190,10 +178,27 @@ fn inject_trait(
     Ok(Item::Trait(item))
 }
 
+/// Fails for `#[mock(eager)]` above `#[async_trait]`, which would expand the methods after they were made eager.
+fn check_eager(args: &MockArgs, attrs: &[Attribute]) -> Result<()> {
+    let async_trait = attrs.iter().find(|attr| {
+        let segment = attr.path().segments.last();
+        segment.map_or(false, |segment| segment.ident == "async_trait")
+    });
+
+    match async_trait {
+        Some(attr) if args.eager => Err(Error::new_spanned(
+            attr,
+            "`#[mock(eager)]` must be placed below `#[async_trait]`, to make the methods it expands eager",
+        )),
+        _ => Ok(()),
+    }
+}
+
 fn inject_fn(
     input: ParseStream,
     pub_token: Option<Token![pub]>,
     attrs: Vec<Attribute>,
+    args: &MockArgs,
 ) -> Result<Item> {
     let mut item: ItemFn = input.parse()?;
     item.attrs = attrs;
@@ -204,35 +209,529 @@ fn inject_fn(
 
     let name = item.sig.ident.clone();
 
-    let args = item.sig.inputs.iter().map(|a| {
-        if let syn::FnArg::Typed(pat) = a {
-            let pat = &pat.pat;
-            quote!(#pat)
-        } else {
-            quote!(self)
+    let eager = if args.eager {
+        Eager::Capturing(Vec::new())
+    } else {
+        Eager::No
+    };
+
+    inject(quote!(#name), None, &mut item.sig, &mut item.block, eager)?;
+
+    Ok(Item::Fn(item))
+}
+
+/// Trait of a mocked method, in an impl of the trait or in the trait itself.
+struct InTrait {
+    path: proc_macro2::TokenStream,
+    /// Generics of the impl or of the trait.
+    generics: Generics,
+}
+
+impl InTrait {
+    /// Generics of the function naming the method from its body, generic over the implementor as `__MockemSelf`,
+    /// and the turbofish calling it.
+    fn naming_generics(&self, sig: &Signature) -> (Generics, proc_macro2::TokenStream) {
+        let path = &self.path;
+        let mut generics = self.generics.clone();
+        let mut method = sig.generics.clone();
+
+        // lifetimes come first, and are left to be inferred
+        let mut params: Punctuated<GenericParam, Token![,]> = generics
+            .params
+            .iter()
+            .chain(method.params.iter())
+            .filter(|param| matches!(param, GenericParam::Lifetime(_)))
+            .cloned()
+            .collect();
+        params.push(parse_quote!(__MockemSelf: ?Sized + #path));
+        let mut args = vec![quote!(Self)];
+
+        for param in generics.params.iter_mut().chain(method.params.iter_mut()) {
+            match param {
+                GenericParam::Lifetime(_) => continue,
+                GenericParam::Type(ty) => {
+                    ty.eq_token = None;
+                    ty.default = None;
+                    args.push(ty.ident.to_token_stream());
+                }
+                GenericParam::Const(c) => {
+                    c.eq_token = None;
+                    c.default = None;
+                    args.push(c.ident.to_token_stream());
+                }
+            }
+            params.push(param.clone());
         }
-    });
 
-    let ret = if let syn::ReturnType::Type(_, ty) = &item.sig.output {
-        quote!(#ty)
+        let mut where_clause = generics.make_where_clause().clone();
+        if let Some(method) = method.where_clause.take() {
+            where_clause.predicates.extend(method.predicates);
+        }
+
+        let mut generics = Generics {
+            lt_token: Some(Default::default()),
+            params,
+            gt_token: Some(Default::default()),
+            where_clause: Some(where_clause),
+        };
+        SelfRenamer.visit_generics_mut(&mut generics);
+
+        (generics, quote!(::<#(#args),*>))
+    }
+}
+
+/// Renames `Self` to `__MockemSelf`.
+struct SelfRenamer;
+
+impl VisitMut for SelfRenamer {
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        if ident == "Self" {
+            *ident = Ident::new("__MockemSelf", ident.span());
+        }
+    }
+}
+
+/// Turbofish for the generic parameters of the method, e.g. `::<T>`.
+///
+/// Lifetimes are left out, to be inferred.
+fn turbofish(sig: &Signature) -> proc_macro2::TokenStream {
+    let generics = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|p| match p {
+            GenericParam::Lifetime(_) => None,
+            GenericParam::Type(ty) => Some(ty.ident.clone()),
+            GenericParam::Const(c) => Some(c.ident.clone()),
+        })
+        .collect::<Vec<_>>();
+
+    if generics.is_empty() {
+        quote!()
     } else {
-        quote!(())
+        quote!(::<#(#generics),*>)
+    }
+}
+
+/// How a mocked function returns the answer of its mock.
+enum Answer {
+    /// As is.
+    Sync,
+    /// Awaited in the function's async body.
+    Async,
+    /// As the future returned by a function returning `impl Future`.
+    Future,
+    /// Boxed, as the future returned by a method expanded by `#[async_trait]`.
+    Boxed,
+}
+
+/// Whether a mocked async function looks up its mock when called, rather than when first polled.
+enum Eager {
+    No,
+    /// In traits and trait impls, where the returned `impl Future` captures every lifetime in scope.
+    InTrait,
+    /// Elsewhere, where the lifetimes to capture, besides those of the signature, are given.
+    Capturing(Vec<Lifetime>),
+}
+
+/// Inject the prelude into the body of a mocked function.
+///
+/// Functions returning `impl Future` look up their mock when called, and return either its answer
+/// or the future of the real body. Eager async functions are desugared to do the same,
+/// instead of looking it up when first polled.
+///
+/// Methods already expanded by `#[async_trait]` get the prelude in their async block,
+/// as if `#[mock]` was placed above it.
+fn inject(
+    path: proc_macro2::TokenStream,
+    in_trait: Option<InTrait>,
+    sig: &mut Signature,
+    block: &mut Block,
+    eager: Eager,
+) -> Result<()> {
+    if let Some((ret, body)) = async_trait_body(sig, block)? {
+        match (eager, body) {
+            (Eager::No, Expr::Async(body)) => {
+                let body = &mut body.block;
+                let mut stmts = prelude(path, in_trait.as_ref(), sig, quote!(#ret), Answer::Async)?;
+                stmts.push(in_async_call(quote!(#ret), &body.stmts));
+                body.stmts = stmts;
+            }
+            (_, body) => {
+                // the mock is picked before the body is boxed
+                *body = parse_quote!(__mockem_fn.in_call::<#ret, _>(__mockem_call, #body));
+
+                let mut stmts = prelude(path, in_trait.as_ref(), sig, quote!(#ret), Answer::Boxed)?;
+                stmts.append(&mut block.stmts);
+                block.stmts = stmts;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let captures = match eager {
+        _ if sig.asyncness.is_none() => None,
+        Eager::No => None,
+        Eager::InTrait => Some(quote!()),
+        Eager::Capturing(mut captured) => {
+            name_lifetimes(sig);
+            captured.extend(lifetimes(&sig.generics));
+
+            if captured.is_empty() {
+                Some(quote!())
+            } else {
+                Some(quote!(+ mockem::Captures<(#(&#captured (),)*)>))
+            }
+        }
     };
 
-    let mut stms = syn::parse2::<Block>(quote!({
-        {
-            use mockem::CallMock;
+    if let Some(captures) = captures {
+        let ret = match &sig.output {
+            ReturnType::Type(_, ty) => quote!(#ty),
+            ReturnType::Default => quote!(()),
+        };
+        let stmts = &block.stmts;
+
+        sig.asyncness = None;
+        sig.output = parse_quote!(-> impl core::future::Future<Output = #ret> #captures);
+        *block = parse_quote!({ async move { #(#stmts)* } });
+    }
+
+    if let Some(ret) = future_output(&sig.output)? {
+        let mut stmts = prelude(path, in_trait.as_ref(), sig, quote!(#ret), Answer::Future)?;
+
+        // the closure keeps `return`s in the body from skipping the wrapping
+        let body = &block.stmts;
+        stmts.push(Stmt::Expr(
+            parse_quote!(mockem::MockedFuture::Real(std::boxed::Box::pin(
+                __mockem_fn.in_call::<#ret, _>(__mockem_call, (move || { #(#body)* })())
+            ))),
+            None,
+        ));
+
+        block.stmts = stmts;
 
-            if  #name .mock_exists(core::marker::PhantomData::<#ret>) {
-                return #name .call_mock((#(#args,)*));
+        return Ok(());
+    }
+
+    let ret = match &sig.output {
+        ReturnType::Type(_, ty) => quote!(#ty),
+        ReturnType::Default => quote!(()),
+    };
+    let answer = if sig.asyncness.is_some() {
+        Answer::Async
+    } else {
+        Answer::Sync
+    };
+
+    let mut stmts = prelude(path, in_trait.as_ref(), sig, ret.clone(), answer)?;
+    if sig.asyncness.is_some() {
+        stmts.push(in_async_call(ret, &block.stmts));
+    } else {
+        stmts.append(&mut block.stmts);
+    }
+    block.stmts = stmts;
+
+    Ok(())
+}
+
+/// The body of an async function, awaited as the rest of the call,
+/// since the call is counted as running only while its future is polled.
+fn in_async_call(ret: proc_macro2::TokenStream, body: &[Stmt]) -> Stmt {
+    Stmt::Expr(
+        parse_quote!(__mockem_fn.in_call::<#ret, _>(__mockem_call, async move { #(#body)* }).await),
+        None,
+    )
+}
+
+/// Lifetime parameters of `generics`.
+fn lifetimes(generics: &Generics) -> Vec<Lifetime> {
+    generics
+        .lifetimes()
+        .map(|param| param.lifetime.clone())
+        .collect()
+}
+
+/// Name the lifetimes elided in the inputs of `sig`, e.g. `&self` as `&'__mockem0 self`,
+/// so the `impl Future` of an eager async function can capture them, as `async fn` does.
+///
+/// Lifetimes hidden in paths, as in `Foo` for `Foo<'_>`, can't be found.
+fn name_lifetimes(sig: &mut Signature) {
+    let mut namer = LifetimeNamer(Vec::new());
+
+    for input in &mut sig.inputs {
+        match input {
+            FnArg::Receiver(receiver) => {
+                namer.visit_type_mut(&mut receiver.ty);
+
+                if let (Some((_, lifetime)), Type::Reference(ty)) =
+                    (&mut receiver.reference, &*receiver.ty)
+                {
+                    lifetime.clone_from(&ty.lifetime);
+                }
             }
+            FnArg::Typed(arg) => namer.visit_type_mut(&mut arg.ty),
         }
-    }))?
-    .stmts;
+    }
+
+    for (i, lifetime) in namer.0.into_iter().enumerate() {
+        sig.generics
+            .params
+            .insert(i, GenericParam::Lifetime(LifetimeParam::new(lifetime)));
+    }
+}
 
-    std::mem::swap(&mut item.block.stmts, &mut stms);
+struct LifetimeNamer(Vec<Lifetime>);
 
-    item.block.stmts.extend(stms);
+impl LifetimeNamer {
+    fn next(&mut self) -> Lifetime {
+        let lifetime = Lifetime::new(
+            &format!("'__mockem{}", self.0.len()),
+            proc_macro2::Span::call_site(),
+        );
+        self.0.push(lifetime.clone());
 
-    Ok(Item::Fn(item))
+        lifetime
+    }
+}
+
+impl VisitMut for LifetimeNamer {
+    fn visit_type_reference_mut(&mut self, ty: &mut TypeReference) {
+        if ty.lifetime.is_none() {
+            ty.lifetime = Some(self.next());
+        }
+
+        visit_mut::visit_type_reference_mut(self, ty);
+    }
+
+    fn visit_lifetime_mut(&mut self, lifetime: &mut Lifetime) {
+        if lifetime.ident == "_" {
+            *lifetime = self.next();
+        }
+    }
+
+    // function types elide lifetimes of their own
+    fn visit_type_bare_fn_mut(&mut self, _: &mut TypeBareFn) {}
+
+    fn visit_parenthesized_generic_arguments_mut(&mut self, _: &mut ParenthesizedGenericArguments) {
+    }
+}
+
+/// Output of a function returning `impl Future<Output = ..>`.
+///
+/// Functions returning other `impl Trait` types can't be mocked, since their mocks couldn't name the type.
+fn future_output(output: &ReturnType) -> Result<Option<Type>> {
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return Ok(None),
+    };
+
+    match &**ty {
+        Type::ImplTrait(impl_trait) => match bounds_output(&impl_trait.bounds) {
+            Some(output) => Ok(Some(output)),
+            None => Err(syn::Error::new_spanned(
+                ty,
+                "`#[mock]` can only mock functions returning `impl Future<Output = ..>` among `impl Trait` types",
+            )),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Output type and boxed async block of a method expanded by `#[async_trait]`,
+/// which returns `Pin<Box<dyn Future<Output = ..> + 'async_trait>>` from `Box::pin(async move { .. })`.
+///
+/// Fails if the method has the `'async_trait` lifetime but not the expected body,
+/// e.g. when another attribute rewrote it, since the mock would never be used.
+fn async_trait_body<'a>(
+    sig: &Signature,
+    block: &'a mut Block,
+) -> Result<Option<(Type, &'a mut Expr)>> {
+    let expanded = sig.generics.params.iter().any(|p| match p {
+        GenericParam::Lifetime(lt) => lt.lifetime.ident == "async_trait",
+        _ => false,
+    });
+
+    if !expanded {
+        return Ok(None);
+    }
+
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) => boxed_future_output(ty),
+        ReturnType::Default => None,
+    };
+
+    let body = match block.stmts.as_mut_slice() {
+        [Stmt::Expr(Expr::Call(call), None)] if call.args.len() == 1 => match &mut call.args[0] {
+            body @ Expr::Async(_) => Some(body),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match (output, body) {
+        (Some(output), Some(body)) => Ok(Some((output, body))),
+        _ => Err(syn::Error::new_spanned(
+            &sig.ident,
+            "`#[mock]` can't find the async body of this method expanded by `#[async_trait]`; place `#[mock]` above `#[async_trait]`",
+        )),
+    }
+}
+
+/// `T` of `Pin<Box<dyn Future<Output = T> + ..>>`.
+fn boxed_future_output(ty: &Type) -> Option<Type> {
+    let pin = generic_arg(ty, "Pin")?;
+    let boxed = generic_arg(pin, "Box")?;
+
+    match boxed {
+        Type::TraitObject(object) => bounds_output(&object.bounds),
+        _ => None,
+    }
+}
+
+/// Single type argument of `ty`, if its last path segment is `name`.
+fn generic_arg<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if segment.ident == name => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `T` of a `Future<Output = T>` bound among `bounds`.
+fn bounds_output(bounds: &Punctuated<TypeParamBound, Token![+]>) -> Option<Type> {
+    for bound in bounds {
+        let segment = match bound {
+            TypeParamBound::Trait(bound) => bound.path.segments.last(),
+            _ => None,
+        };
+
+        let args = match segment {
+            Some(segment) if segment.ident == "Future" => &segment.arguments,
+            _ => continue,
+        };
+
+        if let PathArguments::AngleBracketed(args) = args {
+            for arg in &args.args {
+                if let GenericArgument::AssocType(assoc) = arg {
+                    if assoc.ident == "Output" {
+                        return Some(assoc.ty.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Statements injected at the top of every mocked function.
+///
+/// Unless the function isn't tracked by the mock store,
+/// they count the call as running, record it, and return the mocked value if one exists,
+/// or panic in strict mode if the real body may not run.
+fn prelude(
+    path: proc_macro2::TokenStream,
+    in_trait: Option<&InTrait>,
+    sig: &Signature,
+    ret: proc_macro2::TokenStream,
+    answer: Answer,
+) -> Result<Vec<Stmt>> {
+    let args = sig
+        .inputs
+        .iter()
+        .map(|a| {
+            if let syn::FnArg::Typed(pat) = a {
+                let pat = &pat.pat;
+                quote!(#pat)
+            } else {
+                quote!(self)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // naming a trait method from its own body makes rustc check the `Send` bound of its `impl Future`
+    // while it infers the body, which it can't do; a function generic over the implementor names it instead
+    let mocked_fn = match in_trait {
+        Some(in_trait) => {
+            let (generics, args) = in_trait.naming_generics(sig);
+            let where_clause = &generics.where_clause;
+            let trait_path = in_trait.path.clone().into_iter().map(|token| match token {
+                proc_macro2::TokenTree::Ident(ident) if ident == "Self" => {
+                    proc_macro2::TokenTree::Ident(Ident::new("__MockemSelf", ident.span()))
+                }
+                token => token,
+            });
+            let trait_path = quote!(#(#trait_path)*);
+            let name = &sig.ident;
+            let method_generics = turbofish(sig);
+
+            quote!({
+                fn __mockem_fn_of #generics () -> mockem::MockedFn #where_clause {
+                    mockem::MockedFn::of(&<__MockemSelf as #trait_path>::#name #method_generics)
+                }
+
+                __mockem_fn_of #args ()
+            })
+        }
+        None => quote!(mockem::MockedFn::of(&#path)),
+    };
+
+    // async mocks may answer with a future, awaited in the function's body,
+    // or returned as is by functions returning `impl Future`
+    let answer = match answer {
+        Answer::Sync => quote!(__mockem_fn.call_mock::<_, #ret>(__mockem_mock, (#(#args,)*))),
+        Answer::Async => quote!({
+            let __mockem_answer = __mockem_fn.call_mock_async::<_, #ret>(__mockem_mock, (#(#args,)*));
+            __mockem_fn.in_call(__mockem_call, __mockem_answer).await
+        }),
+        Answer::Future => quote!({
+            let __mockem_answer = __mockem_fn.call_mock_async::<_, #ret>(__mockem_mock, (#(#args,)*));
+            mockem::MockedFuture::Mocked(__mockem_fn.in_call(__mockem_call, __mockem_answer))
+        }),
+        Answer::Boxed => quote!({
+            let __mockem_answer = __mockem_fn.call_mock_async::<_, #ret>(__mockem_mock, (#(#args,)*));
+            std::boxed::Box::pin(__mockem_fn.in_call(__mockem_call, __mockem_answer))
+        }),
+    };
+
+    Ok(syn::parse2::<Block>(quote!({
+        let __mockem_fn = #mocked_fn;
+
+        // lives until the function returns, or its future is returned, so nested calls know their depth
+        let __mockem_call = {
+            std::thread_local! {
+                static __MOCKEM_TRACKED: mockem::TrackedCache = const { mockem::TrackedCache::new() };
+            }
+
+            __mockem_fn.enter_call(&__MOCKEM_TRACKED)
+        };
+
+        // functions that were never mocked skip the rest
+        if __mockem_call.is_some() {
+            #[allow(unused_imports)]
+            use mockem::{DebugSnapshot as _, NoSnapshot as _};
+
+            let __mockem_mock = __mockem_fn.find_mock::<_, #ret>((#(&#args,)*));
+            __mockem_fn.record_call(
+                || std::vec![#((&mockem::Snapshot(&#args)).snapshot()),*],
+                __mockem_mock.is_some(),
+            );
+
+            if let Some(__mockem_mock) = __mockem_mock {
+                return #answer;
+            }
+
+            __mockem_fn.check_real_call();
+        }
+    }))?
+    .stmts)
 }
@@ -0,0 +1,81 @@
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Error, Ident, ItemFn, ReturnType, Token};
+
+/// Arguments of `#[mockem::test]`.
+#[derive(Default)]
+pub struct TestArgs {
+    strict: bool,
+    shared: bool,
+}
+
+impl Parse for TestArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let flags = Punctuated::<Ident, Token![,]>::parse_terminated(input)?;
+        let mut args = Self::default();
+
+        for flag in flags {
+            if flag == "strict" {
+                args.strict = true;
+            } else if flag == "shared" {
+                args.shared = true;
+            } else {
+                return Err(Error::new_spanned(
+                    flag,
+                    "unknown argument, expected `strict` or `shared`",
+                ));
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+/// Wrap the test with a `mockem::TestGuard`, adding `#[test]` if it has no test attribute.
+pub fn expand(args: TestArgs, mut item: ItemFn) -> Result<proc_macro2::TokenStream> {
+    // `#[test]`, `#[tokio::test]` and the like
+    let has_test_attr = item.attrs.iter().any(is_test_attr);
+
+    if !has_test_attr {
+        if item.sig.asyncness.is_some() {
+            return Err(Error::new_spanned(
+                item.sig.fn_token,
+                "async tests need a test attribute with a runtime, e.g. `#[tokio::test]`, after `#[mockem::test]`",
+            ));
+        }
+
+        item.attrs.insert(0, syn::parse_quote!(#[test]));
+    }
+
+    let TestArgs { strict, shared } = args;
+
+    // async tests are wrapped too, so the scope can follow the test's task
+    if shared && item.sig.asyncness.is_some() {
+        let block = &item.block;
+        let ret = match &item.sig.output {
+            ReturnType::Type(_, ty) => quote!(#ty),
+            ReturnType::Default => quote!(()),
+        };
+
+        *item.block = syn::parse_quote!({
+            let __mockem_test = mockem::TestGuard::new(#strict, true);
+            mockem::shared_test::<#ret, _>(async move #block).await
+        });
+    } else {
+        item.block.stmts.insert(
+            0,
+            syn::parse_quote!(let __mockem_test = mockem::TestGuard::new(#strict, #shared);),
+        );
+    }
+
+    Ok(item.into_token_stream())
+}
+
+fn is_test_attr(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .map(|s| s.ident == "test")
+        .unwrap_or(false)
+}
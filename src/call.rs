@@ -0,0 +1,84 @@
+use std::fmt::{self, Debug, Display};
+
+/// A recorded call to a mocked function.
+///
+/// Arguments are captured with their `Debug` output at the time of the call.
+/// Arguments whose types don't implement `Debug` are recorded as `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    name: &'static str,
+    args: Vec<Option<String>>,
+    mocked: bool,
+}
+
+impl Call {
+    pub(crate) fn new(name: &'static str, args: Vec<Option<String>>, mocked: bool) -> Self {
+        Self { name, args, mocked }
+    }
+
+    /// Path of the called function, e.g. `my_crate::foo`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// `Debug` output of each argument, in order; `self` counts as the first argument of methods.
+    pub fn args(&self) -> &[Option<String>] {
+        &self.args
+    }
+
+    /// `Debug` output of the argument at `index`.
+    ///
+    /// Returns `None` if there is no such argument or its type doesn't implement `Debug`.
+    pub fn arg(&self, index: usize) -> Option<&str> {
+        self.args.get(index).and_then(|a| a.as_deref())
+    }
+
+    /// Whether this call was answered by a mock rather than the real function body.
+    pub fn is_mocked(&self) -> bool {
+        self.mocked
+    }
+}
+
+impl Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(arg.as_deref().unwrap_or("_"))?;
+        }
+
+        f.write_str(")")
+    }
+}
+
+// `#[mock]` captures arguments with `(&Snapshot(&arg)).snapshot()`.
+// Method resolution picks `DebugSnapshot` when the argument implements `Debug`,
+// and falls back to `NoSnapshot` through the extra autoref otherwise.
+
+#[doc(hidden)]
+pub struct Snapshot<'a, T: ?Sized>(pub &'a T);
+
+#[doc(hidden)]
+pub trait DebugSnapshot {
+    fn snapshot(&self) -> Option<String>;
+}
+
+impl<T: Debug + ?Sized> DebugSnapshot for Snapshot<'_, T> {
+    fn snapshot(&self) -> Option<String> {
+        Some(format!("{:?}", self.0))
+    }
+}
+
+#[doc(hidden)]
+pub trait NoSnapshot {
+    fn snapshot(&self) -> Option<String>;
+}
+
+impl<T: ?Sized> NoSnapshot for &Snapshot<'_, T> {
+    fn snapshot(&self) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,166 @@
+//! Carry the mock context of a thread into the threads it starts.
+//!
+//! Mocks queued with `MockCall` are kept per thread and their closures aren't `Send`, so they stay on the thread that queued them.
+//! What a thread can hand to the threads it starts is its context:
+//! the shared scope it sees, so mocks queued with `MockShared::mock_shared` answer there,
+//! mocks queued with `MockSend::mock_once_send` and `mock_repeat_send`, which answer there as on the thread that queued them,
+//! and strict mode, with the functions allowed to run their real body.
+//! Functions mocked with `MockCall` in the context panic when called without a shared or `Send` mock,
+//! rather than silently running their real body.
+//!
+//! `mockem::thread::spawn` does this for threads started with it;
+//! thread pools can `capture` the context where work is submitted and `enter` it where the work runs.
+//!
+//! ```rust
+//! # #[mockem::mock]
+//! # fn fetch() -> u8 { 0 }
+//! use mockem::MockShared;
+//!
+//! let _scope = mockem::shared_scope();
+//! fetch.mock_shared(None, || 1);
+//!
+//! let context = mockem::context::capture();
+//! let worker = std::thread::spawn(move || {
+//!     let _context = context.enter();
+//!     fetch()
+//! });
+//!
+//! assert_eq!(worker.join().unwrap(), 1);
+//! ```
+//!
+//! Mocks queued with `MockSend` need no shared scope: the thread that queued them shares them with the threads
+//! its context is entered on, which use them up together, and `mockem::clear_mocks()` on any of them removes them.
+//!
+//! ```rust
+//! # #[mockem::mock]
+//! # fn fetch() -> u8 { 0 }
+//! use mockem::MockSend;
+//!
+//! fetch.mock_once_send(|| 1);
+//!
+//! let context = mockem::context::capture();
+//! let worker = std::thread::spawn(move || {
+//!     let _context = context.enter();
+//!     fetch()
+//! });
+//!
+//! assert_eq!(worker.join().unwrap(), 1);
+//! // used up by the worker
+//! assert_eq!(fetch(), 0);
+//! ```
+
+use std::{any::TypeId, sync::Arc};
+
+use crate::{
+    shared::{self, Scope},
+    MOCK_STORE,
+};
+
+/// Mock context of a thread, captured with `capture`.
+#[derive(Clone, Debug)]
+pub struct MockContext {
+    scope: Option<Arc<Scope>>,
+    // mocks queued with `MockSend`
+    carried: Arc<Scope>,
+    strict: bool,
+    allowed: Vec<TypeId>,
+    // functions mocked with `MockCall`
+    mocked: Vec<TypeId>,
+}
+
+/// Capture the mock context of the current thread.
+pub fn capture() -> MockContext {
+    let (strict, allowed, mocked) = MOCK_STORE.with(|mock_store| {
+        (
+            mock_store.is_strict(),
+            mock_store.allowed(),
+            mock_store.mocked(),
+        )
+    });
+
+    MockContext {
+        scope: shared::current_scope(),
+        carried: shared::carried_scope(),
+        strict,
+        allowed,
+        mocked,
+    }
+}
+
+impl MockContext {
+    /// Enter the context on the current thread, until the returned guard is dropped.
+    pub fn enter(&self) -> ContextGuard {
+        let scope = shared::enter_scope(self.scope.clone());
+        let carried = shared::enter_carried(Some(self.carried.clone()));
+        let (strict, allowed, mocked, tracked) = MOCK_STORE.with(|mock_store| {
+            let allowed = self
+                .allowed
+                .iter()
+                .copied()
+                .filter(|&id| mock_store.allow_real(id))
+                .collect();
+            let mocked = self
+                .mocked
+                .iter()
+                .copied()
+                .filter(|&id| mock_store.mock_elsewhere(id))
+                .collect();
+            // so calls find out they are mocked elsewhere even while unrecorded
+            let tracked = self
+                .mocked
+                .iter()
+                .copied()
+                .filter(|&id| mock_store.track(id))
+                .collect();
+
+            (mock_store.set_strict(self.strict), allowed, mocked, tracked)
+        });
+
+        ContextGuard {
+            scope,
+            carried,
+            strict,
+            allowed,
+            mocked,
+            tracked,
+        }
+    }
+}
+
+/// Guard of an entered `MockContext`, restoring the thread's own context when dropped.
+#[must_use = "the context is left as soon as the guard is dropped"]
+pub struct ContextGuard {
+    scope: Option<Arc<Scope>>,
+    carried: Option<Arc<Scope>>,
+    strict: bool,
+    // functions allowed by the context only
+    allowed: Vec<TypeId>,
+    // functions marked as mocked by the context only
+    mocked: Vec<TypeId>,
+    // functions tracked by the context only
+    tracked: Vec<TypeId>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        shared::enter_scope(self.scope.take());
+        shared::enter_carried(self.carried.take());
+
+        // the store is already gone if the thread is shutting down
+        let _ = MOCK_STORE.try_with(|mock_store| {
+            mock_store.set_strict(self.strict);
+
+            for &id in &self.allowed {
+                mock_store.disallow_real(id);
+            }
+
+            for &id in &self.mocked {
+                mock_store.unmock_elsewhere(id);
+            }
+
+            for &id in &self.tracked {
+                mock_store.untrack(id);
+            }
+        });
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+/// Handle to a deferred mock, added with `MockDeferred::mock_deferred`.
+///
+/// The call the mock answers waits until the handle completes it.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # async fn fetch() -> u8 { 0 }
+/// # async fn test() {
+/// use mockem::{DeferredStatus, MockDeferred};
+///
+/// let deferred = fetch.mock_deferred();
+/// let fetched = tokio::spawn(fetch());
+///
+/// tokio::task::yield_now().await;
+/// assert_eq!(deferred.status(), DeferredStatus::Waiting);
+///
+/// deferred.complete(1);
+/// assert_eq!(fetched.await.unwrap(), 1);
+/// assert_eq!(deferred.status(), DeferredStatus::Completed);
+/// # }
+/// ```
+pub struct Deferred<O>(Arc<Mutex<DeferredState<O>>>);
+
+/// Where the call answered by a deferred mock is at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeferredStatus {
+    /// The mock didn't answer a call yet.
+    NotCalled,
+    /// The call is waiting for its value.
+    Waiting,
+    /// The call returned its value.
+    Completed,
+    /// The call was dropped before returning, e.g. by a timeout or `select!`.
+    Cancelled,
+}
+
+struct DeferredState<O> {
+    status: DeferredStatus,
+    polled: bool,
+    value: Option<O>,
+    waker: Option<Waker>,
+}
+
+impl<O> Deferred<O> {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Mutex::new(DeferredState {
+            status: DeferredStatus::NotCalled,
+            polled: false,
+            value: None,
+            waker: None,
+        })))
+    }
+
+    fn state(&self) -> MutexGuard<'_, DeferredState<O>> {
+        lock(&self.0)
+    }
+
+    /// Another handle to the same call.
+    pub(crate) fn share(&self) -> Self {
+        Self(self.0.clone())
+    }
+
+    /// Future answering the call.
+    pub(crate) fn future(&self) -> DeferredFuture<O> {
+        self.state().status = DeferredStatus::Waiting;
+
+        DeferredFuture(self.0.clone())
+    }
+
+    /// Return `value` from the call, now if it is waiting, or as soon as it is made.
+    ///
+    /// Panics if the call already has a value.
+    pub fn complete(&self, value: O) {
+        let waker = {
+            let mut state = self.state();
+
+            assert!(
+                state.value.is_none() && state.status != DeferredStatus::Completed,
+                "deferred call was already completed"
+            );
+
+            state.value = Some(value);
+            state.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Where the call is at.
+    pub fn status(&self) -> DeferredStatus {
+        self.state().status
+    }
+
+    /// Whether the call was polled at least once.
+    pub fn was_polled(&self) -> bool {
+        self.state().polled
+    }
+}
+
+impl<T, E> Deferred<Result<T, E>> {
+    /// Return `Err(error)` from the call, as with `complete`.
+    pub fn fail(&self, error: E) {
+        self.complete(Err(error))
+    }
+}
+
+impl<O> fmt::Debug for Deferred<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+
+        f.debug_struct("Deferred")
+            .field("status", &state.status)
+            .field("polled", &state.polled)
+            .finish()
+    }
+}
+
+/// Future of a call answered by a deferred mock.
+pub(crate) struct DeferredFuture<O>(Arc<Mutex<DeferredState<O>>>);
+
+impl<O> Future for DeferredFuture<O> {
+    type Output = O;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<O> {
+        let mut state = lock(&self.0);
+        state.polled = true;
+
+        match state.value.take() {
+            Some(value) => {
+                state.status = DeferredStatus::Completed;
+                Poll::Ready(value)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<O> Drop for DeferredFuture<O> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.0);
+
+        if state.status == DeferredStatus::Waiting {
+            state.status = DeferredStatus::Cancelled;
+        }
+    }
+}
+
+fn lock<O>(state: &Mutex<DeferredState<O>>) -> MutexGuard<'_, DeferredState<O>> {
+    // a panicking test leaves the state consistent
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
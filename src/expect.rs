@@ -0,0 +1,355 @@
+use std::{
+    any::{type_name, TypeId},
+    cell::{Cell, RefCell},
+    fmt::{self, Display, Write},
+    marker::PhantomData,
+    rc::Rc,
+    thread,
+};
+
+use crate::{
+    add_mock,
+    matchers::{ArgsMatcher, Matching},
+    mock_id,
+    store::Erased,
+    Args, Call, MockCall, MockHandle, MockState, Predicate, Sequence, MOCK_STORE,
+};
+
+/// How many times a function is expected to be called.
+///
+/// ```rust
+/// use mockem::Times;
+///
+/// assert!(Times::exactly(2).contains(2));
+/// assert!(Times::at_least(1).contains(5));
+/// assert!(!Times::at_most(1).contains(2));
+/// assert!(Times::never().contains(0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Times {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Times {
+    /// Exactly `n` calls.
+    pub fn exactly(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    /// `n` or more calls.
+    pub fn at_least(n: usize) -> Self {
+        Self { min: n, max: None }
+    }
+
+    /// `n` or fewer calls.
+    pub fn at_most(n: usize) -> Self {
+        Self {
+            min: 0,
+            max: Some(n),
+        }
+    }
+
+    /// Between `min` and `max` calls, inclusive.
+    pub fn between(min: usize, max: usize) -> Self {
+        assert!(min <= max, "min must not be greater than max");
+
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+
+    /// No calls at all.
+    pub fn never() -> Self {
+        Self::exactly(0)
+    }
+
+    /// Whether `count` calls satisfy this expectation.
+    pub fn contains(&self, count: usize) -> bool {
+        count >= self.min && self.max.map(|max| count <= max).unwrap_or(true)
+    }
+}
+
+impl From<usize> for Times {
+    fn from(n: usize) -> Self {
+        Self::exactly(n)
+    }
+}
+
+impl Display for Times {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (min, Some(max)) if min == max => write!(f, "exactly {}", plural(min)),
+            (0, Some(max)) => write!(f, "at most {}", plural(max)),
+            (min, Some(max)) => write!(f, "between {min} and {}", plural(max)),
+            (min, None) => write!(f, "at least {}", plural(min)),
+        }
+    }
+}
+
+fn plural(n: usize) -> String {
+    if n == 1 {
+        "1 time".to_owned()
+    } else {
+        format!("{n} times")
+    }
+}
+
+/// An expectation registered with `ExpectCalls::expect_calls` or `ExpectCalls::expect`.
+pub(crate) struct ExpectationState {
+    pub(crate) name: &'static str,
+    pub(crate) times: Times,
+    // calls counted since the expectation was registered
+    pub(crate) calls: Cell<usize>,
+    // whether the expectation answers calls with its own mock, and so counts only the calls it answers;
+    // otherwise every recorded call of the function is counted
+    pub(crate) answers: bool,
+    // description of the argument matchers
+    with: Option<String>,
+    // (call index, reason) of calls the argument matchers rejected
+    mismatches: RefCell<Vec<(usize, String)>>,
+    // expectations that must be met before this one is called
+    after: Vec<Rc<ExpectationState>>,
+}
+
+impl ExpectationState {
+    pub(crate) fn new(name: &'static str, times: Times) -> Self {
+        Self {
+            name,
+            times,
+            calls: Cell::new(0),
+            answers: false,
+            with: None,
+            mismatches: RefCell::new(Vec::new()),
+            after: Vec::new(),
+        }
+    }
+
+    /// Whether the expectation can't take more calls without failing.
+    pub(crate) fn is_saturated(&self) -> bool {
+        self.times.max == Some(self.calls.get())
+    }
+
+    fn is_satisfied(&self) -> bool {
+        self.calls.get() >= self.times.min
+    }
+
+    /// Count a call answered by the expectation's mock.
+    ///
+    /// Panics if an expectation it must come after isn't met yet.
+    pub(crate) fn call(&self) {
+        if let Some(before) = self.after.iter().find(|e| !e.is_satisfied()) {
+            panic!(
+                "`{}` was called before `{}` was called {}",
+                self.name, before.name, before.times
+            );
+        }
+
+        self.calls.set(self.calls.get() + 1);
+    }
+
+    /// Remember why the call at `index` didn't match the argument matchers.
+    pub(crate) fn mismatch(&self, index: usize, reason: String) {
+        self.mismatches.borrow_mut().push((index, reason));
+    }
+
+    /// Describe the failure, with the calls recorded for the function, if the expectation isn't met.
+    pub(crate) fn check(&self, calls: &[Call]) -> Option<String> {
+        let count = self.calls.get();
+
+        if self.times.contains(count) {
+            return None;
+        }
+
+        let mut report = format!("`{}` was expected to be called {}", self.name, self.times);
+
+        if let Some(with) = &self.with {
+            let _ = write!(report, " with {with}");
+        }
+
+        let _ = write!(report, ", but was called {}", plural(count));
+
+        let mismatches = self.mismatches.borrow();
+
+        for (i, call) in calls.iter().enumerate() {
+            let _ = write!(report, "\n    {call}");
+
+            if let Some((_, reason)) = mismatches.iter().find(|(index, _)| *index == i) {
+                let _ = write!(report, ": {reason}");
+            }
+        }
+
+        Some(report)
+    }
+}
+
+/// Builder for an expectation that answers the calls it expects, created with `ExpectCalls::expect`.
+///
+/// The expectation is registered by `returning`, which sets the return behaviour.
+/// Until then, `with`, `times`, `after` and `in_sequence` can be chained in any order.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn get(key: &str) -> u32 { 0 }
+/// use mockem::{matchers::*, ExpectCalls, Times};
+///
+/// let expectation = get
+///     .expect()
+///     .with((eq("a"),))
+///     .times(Times::exactly(2))
+///     .returning(|_| 1);
+///
+/// assert_eq!(get("a"), 1);
+/// assert_eq!(get("b"), 0);
+/// assert_eq!(get("a"), 1);
+///
+/// expectation.verify();
+/// ```
+#[must_use = "the expectation is only registered by `returning`"]
+pub struct ExpectationBuilder<'a, F, I> {
+    f: &'a F,
+    times: Times,
+    when: Option<Erased>,
+    with: Option<String>,
+    after: Vec<Rc<ExpectationState>>,
+    sequence: Option<Sequence>,
+    _args: PhantomData<I>,
+}
+
+impl<'a, F, I: Args> ExpectationBuilder<'a, F, I> {
+    pub(crate) fn new(f: &'a F) -> Self {
+        Self {
+            f,
+            times: Times::at_least(1),
+            when: None,
+            with: None,
+            after: Vec::new(),
+            sequence: None,
+            _args: PhantomData,
+        }
+    }
+
+    /// Only expect calls whose arguments match; other calls fall through to the next mock, or to the real function.
+    ///
+    /// `matchers` is a tuple with one matcher from `mockem::matchers` per argument.
+    pub fn with<M: ArgsMatcher<I> + 'static>(mut self, matchers: M) -> Self {
+        self.with = Some(matchers.describe());
+
+        let when: Box<dyn Predicate<I>> = Box::new(Matching(matchers));
+        self.when = Some(Erased::new(when));
+        self
+    }
+
+    /// Expect the function to be called `times`; at least once by default.
+    ///
+    /// Once the expectation has answered the most calls it allows,
+    /// later expectations of the function answer instead, if any match.
+    pub fn times(mut self, times: impl Into<Times>) -> Self {
+        self.times = times.into();
+        self
+    }
+
+    /// Expect the function to be called only after `other` is met.
+    ///
+    /// Calling it before panics.
+    pub fn after(mut self, other: &ExpectationGuard) -> Self {
+        self.after.push(other.expectation.clone());
+        self
+    }
+
+    /// Add the expectation to the end of `sequence`.
+    ///
+    /// It must be called the least number of times expected before the next step of the sequence answers.
+    pub fn in_sequence(mut self, sequence: &Sequence) -> Self {
+        self.sequence = Some(sequence.clone());
+        self
+    }
+
+    /// Answer the expected calls with `with`, and register the expectation.
+    ///
+    /// The expectation is verified when the returned guard is dropped,
+    /// unless it was verified earlier by `verify` or `mockem::verify()`.
+    pub fn returning<O, W, Fut>(self, with: W) -> ExpectationGuard
+    where
+        F: MockCall<I, O, W, Fut>,
+    {
+        let id = mock_id(self.f);
+        let expectation = Rc::new(ExpectationState {
+            answers: true,
+            with: self.with,
+            after: self.after,
+            ..ExpectationState::new(type_name::<F>(), self.times)
+        });
+
+        MOCK_STORE.with(|mock_store| mock_store.expect(id, expectation.clone()));
+
+        let mut state = MockState::new(type_name::<F>(), None);
+        state.expectation = Some(expectation.clone());
+        state.sequence = RefCell::new(
+            self.sequence
+                .map(|sequence| sequence.join(type_name::<F>(), self.times.min)),
+        );
+
+        let mock = add_mock(id, self.f.erase_mock(with), self.when, state);
+
+        ExpectationGuard {
+            id,
+            expectation,
+            mock,
+        }
+    }
+}
+
+/// Guard of an expectation built with `ExpectCalls::expect`.
+///
+/// Dropping the guard verifies the expectation, unless it was already verified, and removes its mock.
+#[must_use = "the expectation is verified as soon as the guard is dropped"]
+pub struct ExpectationGuard {
+    id: TypeId,
+    expectation: Rc<ExpectationState>,
+    mock: MockHandle,
+}
+
+impl ExpectationGuard {
+    /// Number of calls the expectation answered so far.
+    pub fn call_count(&self) -> usize {
+        self.expectation.calls.get()
+    }
+
+    /// Verify the expectation now.
+    ///
+    /// Panics with a report of the calls made to the function if it isn't met.
+    pub fn verify(&self) {
+        // already verified by `mockem::verify()` or `clear_mocks` if it's no longer registered
+        let registered = MOCK_STORE
+            .try_with(|mock_store| mock_store.remove_expectation(self.id, &self.expectation))
+            .unwrap_or(false);
+
+        if registered {
+            let calls = MOCK_STORE.with(|mock_store| mock_store.calls(self.id));
+
+            assert_verified(self.expectation.check(&calls).into_iter().collect());
+        }
+    }
+}
+
+impl Drop for ExpectationGuard {
+    fn drop(&mut self) {
+        self.mock.cancel();
+
+        if !thread::panicking() {
+            self.verify();
+        }
+    }
+}
+
+/// Panic with every failure in `reports`, if any.
+pub(crate) fn assert_verified(reports: Vec<String>) {
+    if !reports.is_empty() {
+        panic!("mock expectations failed:\n  {}", reports.join("\n  "));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+/// Gate of a gated mock, added with `MockGated::mock_gated`.
+///
+/// Each call the mock answers parks its thread at the gate until the test releases it with a value,
+/// so tests can line threads up before letting them go, e.g. to reproduce races deterministically.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn submit(id: u32) -> bool { true }
+/// use mockem::MockGated;
+///
+/// let _scope = mockem::shared_scope();
+/// let gate = submit.mock_gated();
+///
+/// let first = mockem::thread::spawn(|| submit(1));
+/// let second = mockem::thread::spawn(|| submit(1));
+///
+/// // both threads are submitting before either is done
+/// gate.wait_parked(2);
+/// gate.release(true);
+/// gate.release(false);
+///
+/// let mut submitted = [first.join().unwrap(), second.join().unwrap()];
+/// submitted.sort();
+/// assert_eq!(submitted, [false, true]);
+/// ```
+pub struct Gate<O>(Arc<GateState<O>>);
+
+struct GateState<O> {
+    calls: Mutex<Calls<O>>,
+    // notified when a call parks, or a value is released
+    changed: Condvar,
+}
+
+struct Calls<O> {
+    parked: usize,
+    // released values not taken by a call yet, oldest first
+    values: VecDeque<O>,
+}
+
+impl<O> Gate<O> {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(GateState {
+            calls: Mutex::new(Calls {
+                parked: 0,
+                values: VecDeque::new(),
+            }),
+            changed: Condvar::new(),
+        }))
+    }
+
+    /// Another handle to the same gate.
+    pub(crate) fn share(&self) -> Self {
+        Self(self.0.clone())
+    }
+
+    fn calls(&self) -> MutexGuard<'_, Calls<O>> {
+        // a panicking thread leaves the calls consistent
+        self.0.calls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Park the calling thread until a value is released, and return it.
+    pub(crate) fn pass(&self) -> O {
+        let mut calls = self.calls();
+        calls.parked += 1;
+        self.0.changed.notify_all();
+
+        loop {
+            if let Some(value) = calls.values.pop_front() {
+                calls.parked -= 1;
+                return value;
+            }
+
+            calls = self
+                .0
+                .changed
+                .wait(calls)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Release one call with `value`: one of the parked calls, or else the next call made.
+    pub fn release(&self, value: O) {
+        self.calls().values.push_back(value);
+        self.0.changed.notify_all();
+    }
+
+    /// Number of calls parked at the gate.
+    pub fn parked(&self) -> usize {
+        self.calls().parked
+    }
+
+    /// Block until at least `n` calls are parked at the gate.
+    pub fn wait_parked(&self, n: usize) {
+        let mut calls = self.calls();
+
+        while calls.parked < n {
+            calls = self
+                .0
+                .changed
+                .wait(calls)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl<O> fmt::Debug for Gate<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calls = self.calls();
+
+        f.debug_struct("Gate")
+            .field("parked", &calls.parked)
+            .field("released", &calls.values.len())
+            .finish()
+    }
+}
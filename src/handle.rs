@@ -0,0 +1,238 @@
+use std::{
+    any::TypeId,
+    cell::{Cell, RefCell},
+    ops::Deref,
+    rc::Rc,
+};
+
+use crate::{
+    expect::ExpectationState, latency::Latency, sequence::SequenceStep, Sequence, MOCK_STORE,
+};
+
+/// Where a mock is queued among the mocks of its function.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Layer {
+    /// Before all other mocks, added by `mock_override`.
+    Override,
+    Queue,
+    /// After all other mocks, added by `mock_fallback`.
+    Fallback,
+}
+
+/// State of a queued mock, shared between the `MockStore` and its `MockHandle`.
+pub(crate) struct MockState {
+    pub(crate) name: &'static str,
+    // `None` means the mock answers indefinitely
+    remaining: Cell<Option<usize>>,
+    cancelled: Cell<bool>,
+    // whether the mock closure is running
+    busy: Cell<bool>,
+    // whether a call whose mock closure panics counts as answered
+    consume_on_panic: Cell<bool>,
+    pub(crate) layer: Layer,
+    // nesting depth of the calls the mock answers, set by `mock_at_depth`
+    pub(crate) depth: Option<usize>,
+    // whether the mock closure returns a future to await, set by `MockAsync`
+    pub(crate) awaits: bool,
+    // time the answers to async calls take
+    pub(crate) latency: Cell<Latency>,
+    // set for mocks added by `ExpectationBuilder::returning`
+    pub(crate) expectation: Option<Rc<ExpectationState>>,
+    pub(crate) sequence: RefCell<Option<SequenceStep>>,
+}
+
+impl MockState {
+    pub(crate) fn new(name: &'static str, repeat: Option<usize>) -> Self {
+        Self {
+            name,
+            remaining: Cell::new(repeat),
+            cancelled: Cell::new(false),
+            busy: Cell::new(false),
+            consume_on_panic: Cell::new(false),
+            layer: Layer::Queue,
+            depth: None,
+            awaits: false,
+            latency: Cell::default(),
+            expectation: None,
+            sequence: RefCell::new(None),
+        }
+    }
+
+    /// Mark the mock closure as running until the returned guard is dropped.
+    pub(crate) fn run(&self) -> Busy<'_> {
+        self.busy.set(true);
+
+        Busy(self)
+    }
+
+    /// Whether the mock closure is running, in which case it can't answer the calls it makes itself.
+    pub(crate) fn is_busy(&self) -> bool {
+        self.busy.get()
+    }
+
+    /// Count one answered call, unless the mock closure panicked and such calls don't count.
+    pub(crate) fn finish(&self, panicked: bool) {
+        if !panicked || self.consume_on_panic.get() {
+            self.consume();
+        }
+    }
+
+    /// Count one answered call.
+    fn consume(&self) {
+        if let Some(remaining) = self.remaining.get() {
+            self.remaining.set(Some(remaining.saturating_sub(1)));
+        }
+    }
+
+    /// Number of calls left to answer, if the mock answers a limited number of them and isn't used up.
+    pub(crate) fn unused(&self) -> Option<usize> {
+        self.remaining
+            .get()
+            .filter(|&n| n > 0 && !self.cancelled.get())
+    }
+
+    /// Whether the mock should answer more calls.
+    pub(crate) fn is_active(&self) -> bool {
+        !self.cancelled.get() && self.remaining.get() != Some(0)
+    }
+}
+
+pub(crate) struct Busy<'a>(&'a MockState);
+
+impl Drop for Busy<'_> {
+    fn drop(&mut self) {
+        self.0.busy.set(false);
+    }
+}
+
+/// Handle to a mock queued with `mock_once` or `mock_repeat`.
+///
+/// Dropping the handle leaves the mock in place; use `guard` to remove it on drop instead.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn foo() -> u8 { 0 }
+/// use mockem::MockCall;
+///
+/// let handle = foo.mock_repeat(Some(2), || 1);
+/// assert_eq!(foo(), 1);
+/// assert_eq!(handle.remaining(), Some(1));
+///
+/// handle.cancel();
+/// assert!(handle.is_consumed());
+/// assert_eq!(foo(), 0);
+/// ```
+pub struct MockHandle {
+    id: TypeId,
+    state: Rc<MockState>,
+}
+
+impl MockHandle {
+    pub(crate) fn new(id: TypeId, state: Rc<MockState>) -> Self {
+        Self { id, state }
+    }
+
+    /// Number of calls the mock will still answer; `None` if it answers indefinitely.
+    pub fn remaining(&self) -> Option<usize> {
+        if self.state.cancelled.get() {
+            Some(0)
+        } else {
+            self.state.remaining.get()
+        }
+    }
+
+    /// Whether the mock will no longer answer calls, because it was used up or cancelled.
+    pub fn is_consumed(&self) -> bool {
+        !self.state.is_active()
+    }
+
+    /// Remove this mock, leaving other mocks of the function in place.
+    pub fn cancel(&self) {
+        self.state.cancelled.set(true);
+
+        // the store is already gone if the thread is shutting down
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.remove_mock(self.id, &self.state));
+    }
+
+    /// Count calls whose mock closure panics as answered, which they aren't by default.
+    ///
+    /// Useful when the mock is meant to panic, e.g. to test `catch_unwind`,
+    /// and the next mock should answer the following call.
+    pub fn consume_on_panic(self) -> Self {
+        self.state.consume_on_panic.set(true);
+        self
+    }
+
+    /// Make the calls this mock answers yield to the executor `n` times before returning.
+    ///
+    /// Only async functions yield; other functions return right away.
+    pub fn yield_times(self, n: usize) -> Self {
+        let mut latency = self.state.latency.get();
+        latency.yields = n;
+
+        self.state.latency.set(latency);
+        self
+    }
+
+    /// Make the calls this mock answers wait for `delay` before returning, after any `yield_times`.
+    ///
+    /// The wait is a `tokio::time::sleep`, so it follows paused test time.
+    /// Only async functions wait; other functions return right away.
+    #[cfg(feature = "tokio")]
+    pub fn with_delay(self, delay: std::time::Duration) -> Self {
+        let mut latency = self.state.latency.get();
+        latency.delay = Some(delay);
+
+        self.state.latency.set(latency);
+        self
+    }
+
+    /// Add this mock to the end of `sequence`.
+    ///
+    /// Mocks answering a limited number of calls must be used up before the next step of the sequence answers;
+    /// indefinite mocks must answer at least once.
+    pub fn in_sequence(self, sequence: &Sequence) -> Self {
+        let required = self.state.remaining.get().unwrap_or(1);
+
+        *self.state.sequence.borrow_mut() = Some(sequence.join(self.state.name, required));
+        self
+    }
+
+    /// Turn this handle into a guard that cancels the mock when dropped.
+    pub fn guard(self) -> MockGuard {
+        MockGuard(self)
+    }
+}
+
+/// Guard that cancels its mock when dropped.
+///
+/// Useful for mocks set up in helper functions, which should not outlive the helper's scope.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn foo() -> u8 { 0 }
+/// use mockem::MockCall;
+///
+/// {
+///     let _guard = foo.mock_repeat(None, || 1).guard();
+///     assert_eq!(foo(), 1);
+/// }
+///
+/// assert_eq!(foo(), 0);
+/// ```
+#[must_use = "the mock is cancelled as soon as the guard is dropped"]
+pub struct MockGuard(MockHandle);
+
+impl Deref for MockGuard {
+    type Target = MockHandle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for MockGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
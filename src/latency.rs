@@ -0,0 +1,62 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Time a mock of an async function takes to answer, set with `MockHandle::yield_times` and `MockHandle::with_delay`.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Latency {
+    pub(crate) yields: usize,
+    #[cfg(feature = "tokio")]
+    pub(crate) delay: Option<std::time::Duration>,
+}
+
+/// Future waiting out a `Latency`: it yields first, then sleeps.
+pub(crate) struct Wait {
+    yields: usize,
+    #[cfg(feature = "tokio")]
+    delay: Option<std::time::Duration>,
+    // created on the first poll after yielding, so it starts when the wait does
+    #[cfg(feature = "tokio")]
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl Wait {
+    pub(crate) fn new(latency: Latency) -> Self {
+        Self {
+            yields: latency.yields,
+            #[cfg(feature = "tokio")]
+            delay: latency.delay,
+            #[cfg(feature = "tokio")]
+            sleep: None,
+        }
+    }
+}
+
+impl Future for Wait {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        if this.yields > 0 {
+            this.yields -= 1;
+            cx.waker().wake_by_ref();
+
+            return Poll::Pending;
+        }
+
+        #[cfg(feature = "tokio")]
+        if let Some(delay) = this.delay.take() {
+            this.sleep = Some(Box::pin(tokio::time::sleep(delay)));
+        }
+
+        #[cfg(feature = "tokio")]
+        if let Some(sleep) = &mut this.sleep {
+            return sleep.as_mut().poll(cx);
+        }
+
+        Poll::Ready(())
+    }
+}
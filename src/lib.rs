@@ -68,6 +68,436 @@
 //! }
 //! ```
 //!
+//! Mocks answer in the order they were queued, and each mock answers until it is used up before the next one does,
+//! so `foo.mock_repeat(Some(2), a); foo.mock_once(b)` answers with `a`, `a`, then `b`.
+//! An indefinite mock keeps answering, and hides the mocks queued after it.
+//! A call whose mock closure panics isn't counted, so the mock keeps its place;
+//! call `consume_on_panic` on the mock's handle to count it anyway.
+//!
+//! To layer mocks differently, `mock_override` queues a mock in front of all others,
+//! and `mock_fallback` adds an indefinite mock that only answers calls no other mock answers,
+//! even those queued after it.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str) -> String {
+//!     format!("{a}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     // default for the whole test
+//!     foo.mock_fallback(|_| "default".to_owned());
+//!     // one-off
+//!     foo.mock_once(|_| "once".to_owned());
+//!
+//!     assert_eq!(&foo("bar"), "once");
+//!     assert_eq!(&foo("bar"), "default");
+//! }
+//! ```
+//!
+//! ### Inspecting Calls
+//!
+//! Every call to a mocked function is recorded, whether it was mocked or not.
+//! In hot loops, `mockem::unrecorded` only records the calls of functions expected or spied on with `spy`,
+//! so calls of functions that were never mocked skip the mock store and cost next to nothing.
+//! Arguments are captured with their `Debug` output.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str) -> String {
+//!     format!("{a}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::{MockCall, Spy};
+//!
+//!     foo.mock_once(|a| format!("mocked {a}"));
+//!
+//!     foo("bar");
+//!     foo("baz");
+//!
+//!     assert_eq!(foo.call_count(), 2);
+//!     assert!(foo.calls()[0].is_mocked());
+//!     assert_eq!(foo.last_call().unwrap().arg(0), Some("\"baz\""));
+//! }
+//! ```
+//!
+//! ### Expecting Calls
+//!
+//! Use `expect_calls` to assert how many times a function is called,
+//! and `verify` to check the expectations once the code under test has run.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str) -> String {
+//!     format!("{a}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::{ExpectCalls, Times};
+//!
+//!     foo.expect_calls(Times::exactly(2));
+//!
+//!     foo("bar");
+//!     foo("baz");
+//!
+//!     // panics with a report of the calls made if the expectation is not met;
+//!     // use `mockem::verify()` to verify all functions at once
+//!     foo.verify();
+//! }
+//! ```
+//!
+//! ### Cancelling Mocks
+//!
+//! `mock_once` and `mock_repeat` return a `MockHandle`, which can report how many calls the mock will
+//! still answer, or cancel just that mock.
+//! Turn it into a `MockGuard` to cancel the mock when the guard is dropped.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str) -> String {
+//!     format!("{a}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     let handle = foo.mock_repeat(Some(2), |a| format!("mocked {a}"));
+//!
+//!     assert_eq!(&foo("bar"), "mocked bar");
+//!     assert_eq!(handle.remaining(), Some(1));
+//!
+//!     handle.cancel();
+//!     assert_eq!(&foo("bar"), "bar");
+//!
+//!     {
+//!         let _guard = foo.mock_repeat(None, |a| format!("guarded {a}")).guard();
+//!         assert_eq!(&foo("bar"), "guarded bar");
+//!     }
+//!
+//!     assert_eq!(&foo("bar"), "bar");
+//! }
+//! ```
+//!
+//! ### Mocking by Arguments
+//!
+//! Use `mock_when` to mock a function only for calls whose arguments match a predicate.
+//! The predicate takes references to the arguments;
+//! calls that don't match fall through to the next mock, or to the real function.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str) -> String {
+//!     format!("{a}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::MockWhen;
+//!
+//!     foo.mock_when(|a| *a == "bar", |_| "mocked bar".to_owned());
+//!     foo.mock_when(|a| a.starts_with("ba"), |a| format!("mocked {a}"));
+//!
+//!     assert_eq!(&foo("bar"), "mocked bar");
+//!     assert_eq!(&foo("baz"), "mocked baz");
+//!     assert_eq!(&foo("foo"), "foo");
+//! }
+//! ```
+//!
+//! Or use `mock_matching` with a tuple of matchers from `mockem::matchers`, one per argument.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo(a: &str, b: u32) -> String {
+//!     format!("{a}{b}")
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::{matchers::*, MockCall};
+//!
+//!     foo.mock_matching((starts_with("ba"), gt(1)), |a, _| format!("mocked {a}"));
+//!
+//!     assert_eq!(&foo("bar", 2), "mocked bar");
+//!     assert_eq!(&foo("bar", 1), "bar1");
+//! }
+//! ```
+//!
+//! ### Building Expectations
+//!
+//! `expect` declares the arguments, call count, ordering and return value of calls in one place.
+//! The expectation is verified when its guard is dropped, or earlier by `verify`.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn connect(addr: &str) -> bool {
+//!     true
+//! }
+//!
+//! #[cfg_attr(test, mockem::mock)]
+//! fn send(msg: &str) -> usize {
+//!     msg.len()
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::{matchers::*, ExpectCalls, Times};
+//!
+//!     let connected = connect.expect().times(Times::exactly(1)).returning(|_| false);
+//!     let _sent = send
+//!         .expect()
+//!         .with((starts_with("hello"),))
+//!         .times(Times::at_least(1))
+//!         // panics if `send` is called before `connect`
+//!         .after(&connected)
+//!         .returning(|_| 0);
+//!
+//!     assert!(!connect("localhost"));
+//!     assert_eq!(send("hello world"), 0);
+//!
+//!     // calls that don't match fall through to the real function
+//!     assert_eq!(send("bye"), 3);
+//! }
+//! ```
+//!
+//! ### Ordering Calls
+//!
+//! Mocks and expectations of different functions can join a `Sequence`,
+//! which panics when their calls come out of order, showing the expected and actual order.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn connect() {}
+//!
+//! #[cfg_attr(test, mockem::mock)]
+//! fn send(msg: &str) {}
+//!
+//! #[cfg_attr(test, mockem::mock)]
+//! fn close() {}
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::{ExpectCalls, MockCall, Sequence, Times};
+//!
+//!     let seq = Sequence::new();
+//!     connect.mock_once(|| ()).in_sequence(&seq);
+//!     let _sent = send.expect().times(Times::at_least(1)).in_sequence(&seq).returning(|_| ());
+//!     close.mock_once(|| ()).in_sequence(&seq);
+//!
+//!     connect();
+//!     send("hello");
+//!     close();
+//!
+//!     // panics if a mock in the sequence was not used up
+//!     seq.verify();
+//! }
+//! ```
+//!
+//! ### Isolating Tests
+//!
+//! Mocks live in a thread local, so tests that share a thread share them too.
+//! `#[mockem::test]` clears them before the test, and fails the test if expectations are unmet
+//! or a `mock_once`/`mock_repeat(Some(n), ..)` mock was not used up.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn foo() -> String {
+//!     "foo".to_owned()
+//! }
+//!
+//! #[mockem::test]
+//! fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     foo.mock_once(|| "mockem".to_owned());
+//!
+//!     assert_eq!(foo(), "mockem");
+//! }
+//!
+//! // async tests need the runtime's test attribute after `#[mockem::test]`
+//! #[mockem::test]
+//! #[tokio::test]
+//! async fn test_async() {}
+//! ```
+//!
+//! ### Strict Mode
+//!
+//! In strict mode, a mocked function panics instead of running its real body,
+//! so a test can't do real I/O by accident.
+//! Use `mockem::strict(|| ..)` or `#[mockem::test(strict)]`, and `allow_real` for functions that may run for real.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn fetch(url: &str) -> String {
+//!     unimplemented!("real network call")
+//! }
+//!
+//! #[cfg_attr(test, mockem::mock)]
+//! fn now() -> u64 {
+//!     0
+//! }
+//!
+//! #[mockem::test(strict)]
+//! fn test_fn() {
+//!     use mockem::{AllowReal, MockCall};
+//!
+//!     now.allow_real();
+//!     fetch.mock_once(|_| "mockem".to_owned());
+//!
+//!     assert_eq!(fetch("https://example.com"), "mockem");
+//!     assert_eq!(now(), 0);
+//! }
+//! ```
+//!
+//! ### Calling the Original
+//!
+//! `mock_wrap` passes the original function to the mock closure, to change or observe the real result.
+//! It is only available for functions that aren't async.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn read(path: &str) -> Vec<u8> {
+//!     path.as_bytes().to_vec()
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::MockWrap;
+//!
+//!     read.mock_wrap(|original, path| {
+//!         let mut bytes = original(path);
+//!         bytes[0] = 0;
+//!         bytes
+//!     });
+//!
+//!     assert_eq!(read("abc"), [0, b'b', b'c']);
+//! }
+//! ```
+//!
+//! ### Recursive Functions
+//!
+//! A mock keeps its place in the queue while its closure runs, but doesn't answer the calls the closure makes to the same function;
+//! those see the rest of the queue, or the real function.
+//! To mock only some levels of a recursive function, `mock_at_depth` mocks the calls made at a nesting depth,
+//! with the outermost call at depth 0. Async calls count as running only while their future is polled,
+//! so calls awaited side by side, e.g. with `join!`, are at the same depth.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn count_nodes(depth: u32) -> u32 {
+//!     if depth == 0 { 1 } else { 1 + 2 * count_nodes(depth - 1) }
+//! }
+//!
+//! #[test]
+//! fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     // prune the tree below the second level
+//!     count_nodes.mock_at_depth(2, |_| 0);
+//!
+//!     assert_eq!(count_nodes(5), 3);
+//! }
+//! ```
+//!
+//! ### Sharing Mocks Across Threads
+//!
+//! Mocks are kept per thread, so threads spawned by the code under test don't see them.
+//! `mock_shared` takes a `Send + 'static` closure and queues it in a shared scope instead,
+//! where it answers calls from every thread in the scope, after any mock queued on the calling thread.
+//!
+//! Shared mocks need a shared scope, from `#[mockem::test(shared)]` or `mockem::shared_scope()`,
+//! and are removed when it ends; `#[mockem::test(shared)]` fails the test if a `mock_shared(Some(n), ..)` mock was not used up.
+//! Only the threads and tasks in a scope see its mocks:
+//! the thread creating it, and those it starts with `mockem::thread::spawn`, so tests using shared mocks run in parallel.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn fetch(url: &str) -> String {
+//!     format!("real {url}")
+//! }
+//!
+//! #[mockem::test(shared)]
+//! fn test_fn() {
+//!     use mockem::MockShared;
+//!
+//!     fetch.mock_shared(None, |_| "mockem".to_owned());
+//!
+//!     let fetched = mockem::thread::spawn(|| fetch("https://example.com"));
+//!
+//!     assert_eq!(fetched.join().unwrap(), "mockem");
+//! }
+//! ```
+//!
+//! Threads started with `mockem::thread::spawn` carry the context of the thread starting them:
+//! its shared scope, the mocks it queued with `mock_once_send` and `mock_repeat_send`,
+//! and strict mode with the functions allowed to run their real body.
+//! Thread pools can do the same with `mockem::context::capture` where work is submitted and `enter` where it runs;
+//! other threads don't see shared mocks.
+//! Mocks queued with `MockCall` stay on their own thread: in the threads started with its context,
+//! the functions they mock panic when called without a shared or `Send` mock, rather than running their real body.
+//!
+//! Without a shared scope, `MockSend` queues `Send + 'static` closures that the thread shares with the threads it carries its context to.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn now() -> u64 {
+//!     0
+//! }
+//!
+//! #[mockem::test]
+//! fn test_fn() {
+//!     use mockem::MockSend;
+//!
+//!     now.mock_repeat_send(Some(2), || 1);
+//!
+//!     assert_eq!(mockem::thread::spawn(now).join().unwrap(), 1);
+//!     assert_eq!(now(), 1);
+//!     assert_eq!(now(), 0);
+//! }
+//! ```
+//!
+//! Threads that never enter a scope, such as those started with plain `std::thread::spawn`, see no shared mocks,
+//! so tests running in parallel don't see each other's. Code that starts threads of its own can enter a captured context in them,
+//! e.g. behind `#[cfg(test)]`.
+//!
+//! On multi-thread runtimes, where a task can move between worker threads, the `tokio` feature adds task scopes:
+//! with it, async `#[mockem::test(shared)]` tests run in a scope attached to the test's task,
+//! which `mockem::tokio::spawn` and `mockem::tokio::spawn_blocking` pass on to child tasks along with the rest of the context.
+//! Like other scopes, task scopes don't see each other's mocks.
+//! Mocks queued with `MockCall` in a task scope follow the task as well:
+//! they are kept, with the task's recorded calls and expectations, in the scope, which hands them to the worker thread polling the task.
+//!
+//! To line threads up deterministically, e.g. to reproduce a race, `mock_gated` mocks a function on every thread of the scope
+//! with calls that park at the returned `Gate` until the test releases them with a value.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! fn reserve(id: u32) -> bool {
+//!     true
+//! }
+//!
+//! #[mockem::test(shared)]
+//! fn test_fn() {
+//!     use mockem::MockGated;
+//!
+//!     let gate = reserve.mock_gated();
+//!     let threads = [mockem::thread::spawn(|| reserve(1)), mockem::thread::spawn(|| reserve(1))];
+//!
+//!     // both threads are reserving at the same time
+//!     gate.wait_parked(2);
+//!     gate.release(true);
+//!     gate.release(false);
+//!
+//!     let reserved = threads.map(|thread| thread.join().unwrap());
+//!     assert_eq!(reserved.iter().filter(|&&r| r).count(), 1);
+//! }
+//! ```
 //!
 //!
 //! ## Impl Blocks
@@ -135,7 +565,7 @@
 //! }
 //!
 //! // also works with async_trait
-//! // but you must place #[mock] above #[async_trait]
+//! // with #[mock] placed either above or below #[async_trait]
 //! #[cfg_attr(test, mockem::mock)]
 //! #[async_trait]
 //! impl Baz for Foo {
@@ -158,46 +588,803 @@
 //!     assert_eq!(&bar().await, "Hello, mockem and mockem2!");
 //! }
 //! ```
+//!
+//! Native `async fn` in traits and methods returning `impl Future<Output = T>` (e.g. with `+ Send`) work too,
+//! in impls and in trait default methods.
+//! Functions returning `impl Future` pick their mock when called rather than when first polled.
+//!
+//! ```rust
+//! use std::future::Future;
+//!
+//! struct Foo;
+//!
+//! #[cfg_attr(test, mockem::mock)]
+//! trait Load {
+//!     async fn load(&self) -> String {
+//!         format!("load")
+//!     }
+//!
+//!     fn load_send(&self) -> impl Future<Output = String> + Send {
+//!         async { format!("load_send") }
+//!     }
+//! }
+//!
+//! impl Load for Foo {}
+//!
+//! #[tokio::test]
+//! async fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     <Foo as Load>::load.mock_once(|_| "mockem".to_owned());
+//!     <Foo as Load>::load_send.mock_once(|_| "mockem2".to_owned());
+//!
+//!     assert_eq!(Foo.load().await, "mockem");
+//!     assert_eq!(tokio::spawn(Foo.load_send()).await.unwrap(), "mockem2");
+//! }
+//! ```
+//!
+//! Other async functions pick their mock when first polled, so a mock added or cleared between calling the function
+//! and awaiting its future changes its answer. With `#[mock(eager)]`, they pick it when called instead,
+//! e.g. for tests creating several futures before awaiting them with `join!`.
+//! Lifetimes elided in the paths of their arguments must then be written out, e.g. `Foo<'_>`.
+//! With `#[async_trait]`, `#[mock(eager)]` goes below it, to see the methods it expands.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock(eager))]
+//! async fn fetch(id: u32) -> u32 {
+//!     id
+//! }
+//!
+//! #[tokio::test]
+//! async fn test_fn() {
+//!     use mockem::MockCall;
+//!
+//!     fetch.mock_once(|_| 10);
+//!     let (first, second) = (fetch(1), fetch(2));
+//!     // doesn't change the calls already made
+//!     fetch.mock_once(|_| 30);
+//!
+//!     assert_eq!(tokio::join!(first, second), (10, 2));
+//!     assert_eq!(fetch(3).await, 30);
+//! }
+//! ```
+//!
+//! To await inside a mock, e.g. another mocked function, a channel or a timer,
+//! `mock_once_async` and `mock_repeat_async` take a closure returning a `Send + 'static` future, which the mocked function awaits;
+//! the future owns what it uses, since it may outlive the arguments of the call.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! async fn fetch(id: u32) -> u32 {
+//!     id
+//! }
+//!
+//! #[tokio::test(start_paused = true)]
+//! async fn test_fn() {
+//!     use mockem::MockAsync;
+//!
+//!     fetch.mock_once_async(|id| async move {
+//!         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+//!         id + 1
+//!     });
+//!
+//!     assert_eq!(fetch(1).await, 2);
+//! }
+//! ```
+//!
+//! To test timeouts and retries, the handle of a mock can make async calls take time:
+//! `yield_times(n)` yields to the executor `n` times before answering, and with the `tokio` feature,
+//! `with_delay(duration)` sleeps with `tokio::time::sleep`, so paused test time applies.
+//! `mock_pending` answers the next call with a future that never resolves.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! async fn fetch(id: u32) -> u32 {
+//!     id
+//! }
+//!
+//! #[tokio::test(start_paused = true)]
+//! async fn test_fn() {
+//!     use mockem::{MockCall, MockPending};
+//!     use std::time::Duration;
+//!
+//!     fetch.mock_pending();
+//!     fetch.mock_once(|_| 2).yield_times(3);
+//!
+//!     assert!(tokio::time::timeout(Duration::from_secs(1), fetch(1)).await.is_err());
+//!     assert_eq!(fetch(1).await, 2);
+//! }
+//! ```
+//!
+//! For races and cancellation, `mock_deferred` answers the next call with a future that waits until the test completes it
+//! through the returned `Deferred` handle, which also tells whether the call is waiting, completed, or was cancelled.
+//!
+//! ```rust
+//! #[cfg_attr(test, mockem::mock)]
+//! async fn fetch(id: u32) -> u32 {
+//!     id
+//! }
+//!
+//! #[tokio::test]
+//! async fn test_fn() {
+//!     use mockem::{DeferredStatus, MockDeferred};
+//!
+//!     let deferred = fetch.mock_deferred();
+//!     let fetched = tokio::spawn(fetch(1));
+//!
+//!     tokio::task::yield_now().await;
+//!     assert_eq!(deferred.status(), DeferredStatus::Waiting);
+//!
+//!     deferred.complete(2);
+//!     assert_eq!(fetched.await.unwrap(), 2);
+//! }
+//! ```
+
+// the examples are written as the tests users would write
+#![allow(clippy::test_attr_in_doctest)]
 
 use std::{
-    any::{Any, TypeId},
+    any::{type_name, Any, TypeId},
     future::Future,
-    marker::PhantomData,
-    mem::transmute,
+    pin::Pin,
     rc::Rc,
+    task::{Context, Poll},
+    thread::LocalKey,
 };
 
-mod store;
-use store::MockStore;
+mod call;
+pub mod context;
+mod deferred;
+mod expect;
+mod gate;
+mod handle;
+mod latency;
+pub mod matchers;
+mod sequence;
+mod shared;
+mod store;
+mod test_guard;
+pub mod thread;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+pub use call::Call;
+#[doc(hidden)]
+pub use call::{DebugSnapshot, NoSnapshot, Snapshot};
+pub use deferred::{Deferred, DeferredStatus};
+pub use expect::{ExpectationBuilder, ExpectationGuard, Times};
+pub use gate::Gate;
+use handle::{Layer, MockState};
+pub use handle::{MockGuard, MockHandle};
+use latency::{Latency, Wait};
+use matchers::{ArgsMatcher, Matching};
+pub use sequence::Sequence;
+use shared::Signature;
+pub use shared::{shared_scope, SharedScope};
+#[doc(hidden)]
+pub use shared::{SharedErased, SharedReturn};
+#[doc(hidden)]
+pub use store::TrackedCache;
+use store::{Erased, MockReturn, MockStore};
+
+pub use mockem_derive::{mock, test};
+#[doc(hidden)]
+pub use test_guard::TestGuard;
+
+thread_local! {
+    static MOCK_STORE: MockStore = MockStore::default()
+}
+
+/// Clear all mocks, recorded calls and expectations in the ThreadLocal; only necessary if tests share threads.
+///
+/// `#[mockem::test]` clears them before each test.
+pub fn clear_mocks() {
+    MOCK_STORE.with(|mock_store| mock_store.clear());
+    shared::clear_carried();
+}
+
+/// Verify the call count expectations of all functions in the ThreadLocal.
+///
+/// Panics with a report of every unmet expectation.
+/// Verified expectations are removed.
+pub fn verify() {
+    expect::assert_verified(MOCK_STORE.with(|mock_store| mock_store.verify_all()))
+}
+
+/// Run `f` in strict mode, in which mocked functions panic instead of running their real body,
+/// unless they were allowed to with `AllowReal::allow_real`.
+///
+/// `#[mockem::test(strict)]` runs the whole test in strict mode.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn read_config() -> String { std::fs::read_to_string("config.toml").unwrap() }
+/// use mockem::MockCall;
+///
+/// let config = mockem::strict(|| {
+///     read_config.mock_once(|| "mocked".to_owned());
+///
+///     read_config()
+/// });
+/// assert_eq!(config, "mocked");
+/// ```
+pub fn strict<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = MOCK_STORE.try_with(|mock_store| mock_store.set_strict(self.0));
+        }
+    }
+
+    let _restore = Restore(MOCK_STORE.with(|mock_store| mock_store.set_strict(true)));
+
+    f()
+}
+
+/// Run the body of an async `#[mockem::test(shared)]` test in the shared scope of its `TestGuard`,
+/// attached to the test's task with the `tokio` feature.
+#[doc(hidden)]
+pub async fn shared_test<O, F: Future<Output = O>>(future: F) -> O {
+    #[cfg(feature = "tokio")]
+    {
+        let scope = shared::thread_scope().expect("`TestGuard` puts the test in a shared scope");
+
+        // the task takes the thread's store, set up by the guard, and gives it back to be checked
+        let store = MockStore::default();
+        MOCK_STORE.with(|mock_store| mock_store.swap(&store));
+
+        let (output, store) = tokio::in_scope(scope, store, future).await;
+        MOCK_STORE.with(|mock_store| mock_store.swap(&store));
+
+        output
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    future.await
+}
+
+/// Queue a mock for the function with the given id.
+fn add_mock(id: TypeId, with: Erased, when: Option<Erased>, state: MockState) -> MockHandle {
+    let state = Rc::new(state);
+    let mock = MockReturn {
+        with,
+        when,
+        state: state.clone(),
+    };
+
+    // like shared mocks, mocks repeated 0 times answer no calls
+    if state.is_active() {
+        MOCK_STORE.with(|mock_store| mock_store.add(id, mock));
+    }
+
+    MockHandle::new(id, state)
+}
+
+/// Find the first mock of the function with the given id that matches the arguments.
+///
+/// Expectations that can't take more calls are skipped,
+/// unless no other mock matches, in which case the first of them answers and fails verification.
+fn find_mock<I: Args>(id: TypeId, args: I::Refs<'_>) -> Option<MockReturn> {
+    // the call entered with `enter_call` before looking for a mock
+    let depth = MOCK_STORE.with(|mock_store| mock_store.depth(id).saturating_sub(1));
+    let mut saturated = None;
+    let mut index = 0;
+
+    // predicates are user code, so they must run without the store borrowed
+    while let Some((state, when)) = MOCK_STORE.with(|mock_store| mock_store.candidate(id, index)) {
+        index += 1;
+
+        // a mock doesn't answer the calls its own closure makes
+        if state.is_busy() || state.depth.is_some_and(|d| d != depth) {
+            continue;
+        }
+
+        let matches = match when {
+            // SAFETY: the predicate is only used for this call, and mocks don't keep the arguments (see `MockCall`)
+            Some(when) => match unsafe { when.get::<dyn Predicate<I>>() }
+                .unwrap_or_else(|| wrong_type(&state, &when, type_name::<dyn Predicate<I>>()))
+                .try_borrow_mut()
+            {
+                Ok(mut when) => {
+                    let matches = when.matches(args);
+
+                    if let (false, Some(expectation)) = (matches, &state.expectation) {
+                        if let Some(reason) = when.mismatch(args) {
+                            // the call is recorded after this, at the current call count
+                            let index = MOCK_STORE.with(|mock_store| mock_store.call_count(id));
+                            expectation.mismatch(index, reason);
+                        }
+                    }
+
+                    matches
+                }
+                // the predicate is calling this function itself
+                Err(_) => false,
+            },
+            None => true,
+        };
+
+        if !matches {
+            continue;
+        }
+
+        match &state.expectation {
+            Some(expectation) if expectation.is_saturated() => {
+                saturated.get_or_insert(state);
+            }
+            _ => return select_mock(id, state),
+        }
+    }
+
+    saturated.and_then(|state| select_mock(id, state))
+}
+
+/// Get a mock chosen by `find_mock`, counting the call if it answers for a sequence or an expectation.
+fn select_mock(id: TypeId, state: Rc<MockState>) -> Option<MockReturn> {
+    if let Some(step) = &*state.sequence.borrow() {
+        step.call();
+    }
+
+    if let Some(expectation) = &state.expectation {
+        expectation.call();
+    }
+
+    MOCK_STORE.with(|mock_store| mock_store.get(id, &state))
+}
+
+/// Answer a call with a mock found by `find_mock`, and remove it from the queue once it is used up.
+///
+/// The mock keeps its place in the queue while its closure runs,
+/// and if the closure panics, the call isn't counted unless the mock consumes on panic.
+///
+/// `C` must be the closure type the mock was added with.
+fn call_mock<C: ?Sized, O>(id: TypeId, mock: MockReturn, call: impl FnOnce(&mut C) -> O) -> O {
+    // counts the call when dropped, even if the closure panics
+    struct Answer {
+        id: TypeId,
+        state: Rc<MockState>,
+        returned: bool,
+    }
+
+    impl Drop for Answer {
+        fn drop(&mut self) {
+            self.state.finish(!self.returned);
+
+            if !self.state.is_active() {
+                let _ =
+                    MOCK_STORE.try_with(|mock_store| mock_store.remove_mock(self.id, &self.state));
+            }
+        }
+    }
+
+    let mut answer = Answer {
+        id,
+        state: mock.state.clone(),
+        returned: false,
+    };
+    let _busy = mock.state.run();
+
+    // SAFETY: the closure is only used for this call, and mocks don't keep the arguments (see `MockCall`)
+    let with = unsafe { mock.with.get::<C>() }
+        .unwrap_or_else(|| wrong_type(&mock.state, &mock.with, type_name::<C>()));
+    let ret = call(&mut with.borrow_mut());
+
+    answer.returned = true;
+    ret
+}
+
+/// A mock found for a call, from the thread's store or the shared one.
+#[doc(hidden)]
+pub enum FoundMock {
+    Local(LocalMock),
+    Shared(SharedReturn),
+}
+
+impl FoundMock {
+    fn latency(&self) -> Latency {
+        match self {
+            FoundMock::Local(mock) => mock.latency,
+            FoundMock::Shared(_) => Latency::default(),
+        }
+    }
+}
+
+/// A mock selected from the thread's store, kept in the store until the call takes it.
+///
+/// It holds nothing but how the mock answers async calls, so async functions stay `Send` while holding it.
+#[doc(hidden)]
+pub struct LocalMock {
+    awaits: bool,
+    latency: Latency,
+}
+
+impl LocalMock {
+    fn select(mock: MockReturn) -> Self {
+        let awaits = mock.state.awaits;
+        let latency = mock.state.latency.get();
+
+        MOCK_STORE.with(|mock_store| mock_store.push_selected(mock));
+
+        Self { awaits, latency }
+    }
+
+    fn take(self) -> MockReturn {
+        std::mem::forget(self);
+
+        // mocks selected by calls made in between were taken since, so this is the last one
+        MOCK_STORE
+            .with(|mock_store| mock_store.pop_selected())
+            .expect("selected mock is in the store")
+    }
+}
+
+impl Drop for LocalMock {
+    // the call unwound before taking its mock
+    fn drop(&mut self) {
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.pop_selected());
+    }
+}
+
+/// Panic because a mock was added with another type than the call expects,
+/// e.g. an async function mocked with a closure returning a future.
+fn wrong_type(state: &MockState, erased: &Erased, expected: &str) -> ! {
+    panic!(
+        "mock of `{}` has the wrong type: it was added as `{}`, but the call expects `{expected}`",
+        state.name,
+        erased.type_name()
+    )
+}
+
+/// Run `f` without recording the calls of functions that aren't expected or spied on,
+/// so calls of functions that were never mocked skip the mock store, e.g. in hot loops.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn square(n: u64) -> u64 { n * n }
+/// use mockem::Spy;
+///
+/// let sum = mockem::unrecorded(|| (0..1000).map(square).sum::<u64>());
+/// assert_eq!(sum, 332_833_500);
+/// assert_eq!(square.call_count(), 0);
+/// ```
+pub fn unrecorded<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = MOCK_STORE.try_with(|mock_store| mock_store.set_unrecorded(self.0));
+        }
+    }
+
+    let _restore = Restore(MOCK_STORE.with(|mock_store| mock_store.set_unrecorded(true)));
+
+    f()
+}
+
+/// Auto-implemented trait for mocking return values of functions.
+///
+/// Works for:
+/// - functions/methods,
+/// - async functions/methods,
+/// - trait methods, and
+/// - async_trait methods.
+///
+/// The trait is implemented for functions with up to 12 arguments.
+///
+/// Mock closures are typed where they are queued, so for `fn foo(a: &str)`, `foo.mock_once(|a: &'static str| ..)` compiles.
+/// Borrowed arguments only live as long as the call, though: mocks must not keep them.
+pub trait MockCall<I, O, W, Fut>: CallMock<I, O, Fut> {
+    /// Mock the return value of this function.
+    /// This expects a closure with the arguments of the function.
+    ///
+    /// The returned handle can be used to inspect or cancel the mock.
+    fn mock_once(&self, with: W) -> MockHandle {
+        self.mock_repeat(Some(1), with)
+    }
+
+    /// Mock the return value of this function `repeat` times, or indefinitely if `None`.
+    fn mock_repeat(&self, repeat: Option<usize>, with: W) -> MockHandle {
+        add_mock(
+            self.get_mock_id(),
+            self.erase_mock(with),
+            None,
+            MockState::new(type_name::<Self>(), repeat),
+        )
+    }
+
+    /// Mock the return value of this function indefinitely, but only for calls whose arguments match.
+    ///
+    /// `matchers` is a tuple with one matcher from `mockem::matchers` per argument.
+    /// Calls that don't match fall through to the next mock, or to the real function.
+    fn mock_matching<M: ArgsMatcher<I> + 'static>(&self, matchers: M, with: W) -> MockHandle
+    where
+        I: Args,
+    {
+        let when: Box<dyn Predicate<I>> = Box::new(Matching(matchers));
+
+        add_mock(
+            self.get_mock_id(),
+            self.erase_mock(with),
+            Some(Erased::new(when)),
+            MockState::new(type_name::<Self>(), None),
+        )
+    }
+
+    /// Mock the return value of this function indefinitely, but only for calls made at the nesting `depth`.
+    ///
+    /// The outermost call of the function is at depth 0,
+    /// a call it makes to the function itself, directly or not, at depth 1, and so on.
+    fn mock_at_depth(&self, depth: usize, with: W) -> MockHandle {
+        let mut state = MockState::new(type_name::<Self>(), None);
+        state.depth = Some(depth);
+
+        add_mock(self.get_mock_id(), self.erase_mock(with), None, state)
+    }
+
+    /// Mock the return value of this function `repeat` times, or indefinitely if `None`,
+    /// before any mock queued so far.
+    ///
+    /// Useful to override a default set up with `mock_repeat(None, ..)` for a few calls.
+    fn mock_override(&self, repeat: Option<usize>, with: W) -> MockHandle {
+        let mut state = MockState::new(type_name::<Self>(), repeat);
+        state.layer = Layer::Override;
+
+        add_mock(self.get_mock_id(), self.erase_mock(with), None, state)
+    }
+
+    /// Mock the return value of this function indefinitely, for calls no other mock answers.
+    ///
+    /// Fallbacks stay behind mocks queued later, so they can serve as the default for a whole test.
+    fn mock_fallback(&self, with: W) -> MockHandle {
+        let mut state = MockState::new(type_name::<Self>(), None);
+        state.layer = Layer::Fallback;
+
+        add_mock(self.get_mock_id(), self.erase_mock(with), None, state)
+    }
+
+    #[doc(hidden)]
+    fn erase_mock(&self, with: W) -> Erased;
+}
 
-pub use mockem_derive::mock;
+/// Auto-implemented trait for mocking functions only for some arguments.
+pub trait MockWhen<I, O, P, W, Fut>: MockCall<I, O, W, Fut> {
+    /// Mock the return value of this function indefinitely, but only for calls whose arguments match `when`.
+    ///
+    /// `when` is a closure taking references to the arguments.
+    /// Calls that don't match fall through to the next mock, or to the real function.
+    fn mock_when(&self, when: P, with: W) -> MockHandle
+    where
+        I: Args,
+        P: Predicate<I> + 'static,
+    {
+        let when: Box<dyn Predicate<I>> = Box::new(when);
 
-thread_local! {
-    static MOCK_STORE: MockStore = MockStore::default()
+        add_mock(
+            self.get_mock_id(),
+            self.erase_mock(with),
+            Some(Erased::new(when)),
+            MockState::new(type_name::<Self>(), None),
+        )
+    }
 }
 
-/// Clear all mocks in the ThreadLocal; only necessary if tests share threads
-pub fn clear_mocks() {
-    MOCK_STORE.with(|mock_store| mock_store.clear())
+/// Auto-implemented trait for mocking functions with a closure that can call the original.
+///
+/// Only implemented for functions that aren't async.
+pub trait MockWrap<I, O, W>: CallMock<I, O, NotFuture> {
+    /// Mock this function indefinitely with a closure that gets the original function, followed by the arguments.
+    ///
+    /// Calling the original runs the real body, skipping mocks and strict mode for that call,
+    /// so the closure can change or observe the real result.
+    /// The call is recorded once, as mocked.
+    ///
+    /// ```rust
+    /// # #[mockem::mock]
+    /// # fn read(path: &str) -> Vec<u8> { path.as_bytes().to_vec() }
+    /// use mockem::MockWrap;
+    ///
+    /// read.mock_wrap(|original, path| {
+    ///     let mut bytes = original(path);
+    ///     bytes[0] = b'X';
+    ///     bytes
+    /// });
+    ///
+    /// assert_eq!(read("abc"), b"Xbc");
+    /// ```
+    fn mock_wrap(&self, with: W) -> MockHandle;
 }
 
-#[doc(hidden)]
-pub struct MockReturn(Rc<Box<dyn FnMut() -> ()>>, Option<usize>);
+/// Auto-implemented trait for mocking functions on every thread of the shared scope, not just the current one.
+///
+/// Shared mocks live in the current shared scope, so they also answer calls from the threads, thread pools
+/// and `spawn_blocking` closures that the scope's context is passed on to, e.g. with `mockem::thread::spawn`.
+/// Mocks queued with `MockCall` on the calling thread answer before shared mocks.
+pub trait MockShared<I, O, W, Fut>: CallMock<I, O, Fut> {
+    /// Mock the return value of this function `repeat` times, or indefinitely if `None`, on every thread of the scope.
+    ///
+    /// The closure must be `Send + 'static`; calls from several threads at once take turns running it.
+    /// Panics outside a shared scope, from `mockem::shared_scope` or `#[mockem::test(shared)]`.
+    fn mock_shared(&self, repeat: Option<usize>, with: W) {
+        shared::add(
+            self.get_mock_id(),
+            type_name::<Self>(),
+            Signature::of::<I, O>(),
+            self.erase_shared(with),
+            repeat,
+        )
+    }
 
-/// Auto-implemented trait for mocking return values of functions.
+    #[doc(hidden)]
+    fn erase_shared(&self, with: W) -> SharedErased;
+}
+
+/// Auto-implemented trait for mocking functions with `Send` closures, carried into the threads started from this one.
 ///
-/// Works for:
-/// - functions/methods,
-/// - async functions/methods,
-/// - trait methods, and
-/// - async_trait methods.
+/// Unlike mocks queued with `MockCall`, these answer on the threads the current thread's context is entered on,
+/// e.g. with `mockem::thread::spawn`, `mockem::tokio::spawn` or `mockem::context`, without a shared scope.
+/// The threads share them: a `mock_once_send` mock answers a single call, from whichever thread makes it first.
+/// Mocks queued with `MockCall` on the calling thread answer first, then these, then shared mocks.
+pub trait MockSend<I, O, W, Fut>: MockShared<I, O, W, Fut> {
+    /// Mock the return value of this function once, on this thread or the threads it carries its context to.
+    ///
+    /// ```rust
+    /// # #[mockem::mock]
+    /// # fn fetch() -> u8 { 0 }
+    /// use mockem::MockSend;
+    ///
+    /// fetch.mock_once_send(|| 1);
+    ///
+    /// assert_eq!(mockem::thread::spawn(fetch).join().unwrap(), 1);
+    /// assert_eq!(fetch(), 0);
+    /// ```
+    fn mock_once_send(&self, with: W) {
+        self.mock_repeat_send(Some(1), with)
+    }
+
+    /// Mock the return value of this function `repeat` times, or indefinitely if `None`,
+    /// on this thread or the threads it carries its context to.
+    ///
+    /// Removed by `mockem::clear_mocks()` on any of them.
+    fn mock_repeat_send(&self, repeat: Option<usize>, with: W) {
+        shared::add_carried(
+            self.get_mock_id(),
+            type_name::<Self>(),
+            Signature::of::<I, O>(),
+            self.erase_shared(with),
+            repeat,
+        )
+    }
+}
+impl<I, O, W, Fut, F: MockShared<I, O, W, Fut>> MockSend<I, O, W, Fut> for F {}
+
+/// Auto-implemented trait for mocking async functions with closures returning a future.
 ///
-/// The trait is implemented for functions with up to 12 arguments.
-pub trait MockCall<I, O, W, Fut>: CallMock<I, O, Fut> {
-    /// Mock the return value of this function.
-    /// This expects a closure with the arguments of the function.
-    fn mock_once(&self, with: W);
-    fn mock_repeat(&self, repeat: Option<usize>, with: W);
+/// The future is awaited by the mocked function, so it can await other mocked functions, channels or timers.
+/// It must be `'static`, owning what it uses rather than borrowing the arguments.
+/// The call counts as answered once the closure returns its future.
+pub trait MockAsync<I, O, W, Fut, WFut>: CallMock<I, O, Fut> {
+    /// Mock the return value of this async function with a closure returning a future.
+    fn mock_once_async(&self, with: W) -> MockHandle {
+        self.mock_repeat_async(Some(1), with)
+    }
+
+    /// Mock the return value of this async function `repeat` times, or indefinitely if `None`,
+    /// with a closure returning a future.
+    ///
+    /// ```rust
+    /// # #[mockem::mock]
+    /// # async fn fetch(id: u32) -> u32 { id }
+    /// # async fn test() {
+    /// use mockem::MockAsync;
+    ///
+    /// let (tx, rx) = tokio::sync::oneshot::channel();
+    /// let mut rx = Some(rx);
+    /// fetch.mock_once_async(move |_| {
+    ///     let rx = rx.take().unwrap();
+    ///     async move { rx.await.unwrap() }
+    /// });
+    ///
+    /// tx.send(5).unwrap();
+    /// assert_eq!(fetch(1).await, 5);
+    /// # }
+    /// ```
+    fn mock_repeat_async(&self, repeat: Option<usize>, with: W) -> MockHandle {
+        let mut state = MockState::new(type_name::<Self>(), repeat);
+        state.awaits = true;
+
+        add_mock(self.get_mock_id(), self.erase_async(with), None, state)
+    }
+
+    #[doc(hidden)]
+    fn erase_async(&self, with: W) -> Erased;
+}
+
+/// Auto-implemented trait for mocking async functions with calls that never return.
+pub trait MockPending<I, O, Fut>: CallMock<I, O, Fut> {
+    /// Mock the next call of this async function with a future that never resolves,
+    /// e.g. to test timeouts; queue it again for each call that should hang.
+    ///
+    /// ```rust
+    /// # #[mockem::mock]
+    /// # async fn fetch() -> u8 { 0 }
+    /// # async fn test() {
+    /// use mockem::MockPending;
+    ///
+    /// fetch.mock_pending();
+    ///
+    /// let fetched = tokio::time::timeout(std::time::Duration::from_secs(1), fetch()).await;
+    /// assert!(fetched.is_err());
+    /// # }
+    /// ```
+    fn mock_pending(&self) -> MockHandle {
+        let mut state = MockState::new(type_name::<Self>(), Some(1));
+        state.awaits = true;
+
+        add_mock(self.get_mock_id(), self.erase_pending(), None, state)
+    }
+
+    #[doc(hidden)]
+    fn erase_pending(&self) -> Erased;
+}
+
+/// Auto-implemented trait for mocking async functions with calls the test completes.
+pub trait MockDeferred<I, O, Fut>: CallMock<I, O, Fut> {
+    /// Mock the next call of this async function with a future that waits until the returned handle completes it.
+    ///
+    /// The handle also tells whether the call was made, polled, or dropped before completing,
+    /// which makes it useful for race and cancellation tests.
+    fn mock_deferred(&self) -> Deferred<O>
+    where
+        O: Send + 'static,
+    {
+        let deferred = Deferred::new();
+        let mut state = MockState::new(type_name::<Self>(), Some(1));
+        state.awaits = true;
+
+        add_mock(
+            self.get_mock_id(),
+            self.erase_deferred(deferred.share()),
+            None,
+            state,
+        );
+
+        deferred
+    }
+
+    #[doc(hidden)]
+    fn erase_deferred(&self, deferred: Deferred<O>) -> Erased
+    where
+        O: Send + 'static;
+}
+
+/// Auto-implemented trait for mocking functions with calls that wait for the test, on every thread of the shared scope.
+///
+/// Only implemented for functions that aren't async; `MockDeferred` is its async counterpart.
+pub trait MockGated<I, O>: CallMock<I, O, NotFuture> {
+    /// Mock this function indefinitely with calls that park their thread at the returned gate until it releases them.
+    ///
+    /// Gated mocks are shared mocks, so they need a shared scope, as with `MockShared::mock_shared`.
+    fn mock_gated(&self) -> Gate<O>
+    where
+        O: Send + 'static,
+    {
+        let gate = Gate::new();
+
+        shared::add(
+            self.get_mock_id(),
+            type_name::<Self>(),
+            Signature::of::<I, O>(),
+            self.erase_gated(gate.share()),
+            None,
+        );
+
+        gate
+    }
+
+    #[doc(hidden)]
+    fn erase_gated(&self, gate: Gate<O>) -> SharedErased
+    where
+        O: Send + 'static;
 }
 
 /// Clear all mocked return values related to this function.
@@ -211,131 +1398,432 @@ pub trait ClearMocks<I, O, Fut>: CallMock<I, O, Fut> {
 }
 impl<I, O, Fut, F: CallMock<I, O, Fut>> ClearMocks<I, O, Fut> for F {}
 
+/// Inspect the calls made to this function.
+///
+/// Every call is recorded, whether it was answered by a mock or ran the real function body,
+/// except in `mockem::unrecorded`, where only the calls of functions expected or spied on with `spy` are.
+pub trait Spy<I>: MockFn<I> {
+    /// Record the calls made to this function from now on, even in `mockem::unrecorded`.
+    ///
+    /// Recording stops with `mockem::clear_mocks()`.
+    fn spy(&self) {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.spy(id));
+    }
+
+    /// All calls made to this function, oldest first.
+    fn calls(&self) -> Vec<Call> {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.calls(id))
+    }
+
+    /// Number of calls made to this function.
+    fn call_count(&self) -> usize {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.call_count(id))
+    }
+
+    /// The most recent call made to this function.
+    fn last_call(&self) -> Option<Call> {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.last_call(id))
+    }
+
+    /// Forget all calls recorded for this function.
+    fn clear_calls(&self) {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.remove_calls(id));
+    }
+}
+impl<I, F: MockFn<I>> Spy<I> for F {}
+
+/// Expect this function to be called a number of times.
+///
+/// Expectations are only checked by `verify`, or by `mockem::verify()` for all functions.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn foo() {}
+/// use mockem::{ExpectCalls, Times};
+///
+/// foo.expect_calls(Times::exactly(2));
+///
+/// foo();
+/// foo();
+///
+/// foo.verify();
+/// ```
+pub trait ExpectCalls<I>: MockFn<I> {
+    /// Expect this function to be called `times` from now on.
+    fn expect_calls(&self, times: impl Into<Times>) {
+        let id = mock_id(self);
+        let expectation = expect::ExpectationState::new(type_name::<Self>(), times.into());
+
+        MOCK_STORE.with(|mock_store| mock_store.expect(id, Rc::new(expectation)));
+    }
+
+    /// Build an expectation that also mocks the calls it expects.
+    ///
+    /// See `ExpectationBuilder`.
+    fn expect(&self) -> ExpectationBuilder<'_, Self, I>
+    where
+        Self: Sized,
+        I: Args,
+    {
+        ExpectationBuilder::new(self)
+    }
+
+    /// Verify the expectations of this function, and remove them.
+    ///
+    /// Panics with a report of the unmet expectations and the calls that were made.
+    fn verify(&self) {
+        let id = mock_id(self);
+
+        expect::assert_verified(MOCK_STORE.with(|mock_store| mock_store.verify(id)))
+    }
+}
+impl<I, F: MockFn<I>> ExpectCalls<I> for F {}
+
+/// Allow this function to run its real body in strict mode.
+///
+/// The allowlist is emptied by `mockem::clear_mocks()`.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn now() -> u64 { 0 }
+/// use mockem::AllowReal;
+///
+/// now.allow_real();
+///
+/// mockem::strict(|| now());
+/// ```
+pub trait AllowReal<I>: MockFn<I> {
+    fn allow_real(&self) {
+        let id = mock_id(self);
+
+        MOCK_STORE.with(|mock_store| mock_store.allow_real(id));
+    }
+}
+impl<I, F: MockFn<I>> AllowReal<I> for F {}
+
+/// Arguments of a function, as a tuple.
 #[doc(hidden)]
-pub trait CallMock<I, O, Fut> {
-    fn mock_exists(&self, _ret: PhantomData<O>) -> bool {
-        let id = self.get_mock_id();
+pub trait Args {
+    type Refs<'a>: Copy
+    where
+        Self: 'a;
+}
+
+/// Predicate on the arguments of a function, used by `mock_when`.
+#[doc(hidden)]
+pub trait Predicate<I: Args> {
+    fn matches(&mut self, args: I::Refs<'_>) -> bool;
 
-        MOCK_STORE.with(|mock_store| mock_store.mock_exists(id))
+    /// Why the arguments don't match, if the predicate can tell.
+    fn mismatch(&mut self, _args: I::Refs<'_>) -> Option<String> {
+        None
     }
+}
 
-    fn call_mock(&self, input: I) -> O;
+/// Any function that can be mocked, regardless of its return type.
+#[doc(hidden)]
+pub trait MockFn<I> {}
+
+/// Key of a function in the `MockStore`; unique to each function item.
+fn mock_id<F: ?Sized>(_: &F) -> TypeId {
+    (|| ()).type_id()
+}
 
+#[doc(hidden)]
+pub trait CallMock<I, O, Fut> {
     fn get_mock_id(&self) -> TypeId {
-        (|| ()).type_id()
+        mock_id(self)
     }
 }
 
+/// A mocked function, as seen by the code `#[mock]` injects into it.
+///
+/// Calls go through its id rather than through the function item,
+/// so the injected code doesn't need the type of the future an async function returns:
+/// rustc can't always resolve it inside the function's own body.
 #[doc(hidden)]
-pub struct NotFuture;
+#[derive(Clone, Copy)]
+pub struct MockedFn {
+    id: TypeId,
+    name: &'static str,
+}
 
-impl<O, W: FnMut() -> O + 'static, F: Fn() -> O> MockCall<(), O, W, NotFuture> for F {
-    fn mock_once(&self, with: W) {
-        self.mock_repeat(Some(1), with)
+impl MockedFn {
+    pub fn of<F: ?Sized>(f: &F) -> Self {
+        Self {
+            id: mock_id(f),
+            name: type_name::<F>(),
+        }
     }
 
-    fn mock_repeat(&self, repeat: Option<usize>, with: W) {
-        let erased: Box<dyn FnMut() -> O + 'static> = Box::new(with);
-        let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(erased)) };
+    /// Count the call as running until the returned guard is dropped at the end of the function,
+    /// or return `None` if the function isn't tracked, in which case the call skips the store.
+    ///
+    /// `cache` is a thread local of the function's call site.
+    pub fn enter_call(self, cache: &'static LocalKey<TrackedCache>) -> Option<CallGuard> {
+        let id = self.id;
 
         MOCK_STORE.with(|mock_store| {
-            mock_store.add(self.get_mock_id(), MockReturn(transmuted, repeat));
-        });
+            if !cache.with(|cache| mock_store.is_tracked(id, cache)) && !shared::is_mocked(id) {
+                return None;
+            }
+
+            mock_store.enter(id);
+
+            Some(CallGuard(id))
+        })
     }
-}
 
-impl<O, F: Fn() -> O> CallMock<(), O, NotFuture> for F {
-    fn call_mock(&self, _: ()) -> O {
-        let id = self.get_mock_id();
+    /// Find a mock in the thread's store, or else in the shared store.
+    pub fn find_mock<'a, R: ArgRefs<'a>, O>(self, args: R) -> Option<FoundMock> {
+        let id = self.id;
 
-        if let Some(MockReturn(with, repeat)) = MOCK_STORE.with(|mock_store| mock_store.get(id)) {
-            let with: Rc<Box<dyn FnMut() -> O + 'static>> = unsafe { transmute(with) };
-            let mut boxed = Rc::into_inner(with).expect("mock should exist");
-            let ret = boxed();
-
-            if let Some(repeat) = repeat {
-                if repeat > 1 {
-                    let transmuted: Rc<Box<dyn FnMut() -> ()>> =
-                        unsafe { transmute(Rc::new(boxed)) };
-                    MOCK_STORE.with(|mock_store| {
-                        mock_store
-                            .add(self.get_mock_id(), MockReturn(transmuted, Some(repeat - 1)));
-                    });
-                }
-            } else {
-                let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(boxed)) };
-                MOCK_STORE.with(|mock_store| {
-                    mock_store.add(self.get_mock_id(), MockReturn(transmuted, None));
-                });
-            }
+        // `mock_wrap` calling the original
+        if MOCK_STORE.with(|mock_store| mock_store.is_bypassed(id)) {
+            return None;
+        }
+
+        let found = find_mock::<R::Args>(id, args)
+            .map(|mock| FoundMock::Local(LocalMock::select(mock)))
+            .or_else(|| {
+                shared::find_mock(id, Signature::of::<R::Args, O>()).map(FoundMock::Shared)
+            });
 
-            ret
-        } else {
-            panic!("mock should exist")
+        if found.is_none() && MOCK_STORE.with(|mock_store| mock_store.is_mocked_elsewhere(id)) {
+            panic!(
+                "`{}` is mocked on the thread that started this one, but mocks queued with `MockCall` stay on their thread; \
+                 mock it with `mock_shared` to answer calls here",
+                self.name
+            );
         }
+
+        found
     }
-}
 
-impl<O, W: FnMut() -> O + 'static, F: Fn() -> Fut + 'static, Fut: Future<Output = O>>
-    MockCall<(), O, W, Fut> for F
-{
-    fn mock_once(&self, f: W) {
-        self.mock_repeat(Some(1), f)
+    /// Count the call as running again whenever `future`, its body or its mock's answer, is polled,
+    /// instead of until `call` is dropped, since other calls may run between its polls.
+    pub fn in_call<O, F: Future<Output = O>>(
+        self,
+        call: Option<CallGuard>,
+        future: F,
+    ) -> InCall<F> {
+        InCall {
+            id: call.map(|call| call.0),
+            future,
+        }
     }
 
-    fn mock_repeat(&self, repeat: Option<usize>, with: W) {
-        let erased: Box<dyn FnMut() -> O + 'static> = Box::new(with);
-        let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(erased)) };
+    /// Record the call, snapshotting its arguments with `args` only if calls of the function are recorded.
+    pub fn record_call(self, args: impl FnOnce() -> Vec<Option<String>>, mocked: bool) {
+        let id = self.id;
 
         MOCK_STORE.with(|mock_store| {
-            mock_store.add(
-                <Self as CallMock<(), O, Fut>>::get_mock_id(self),
-                MockReturn(transmuted, repeat),
-            );
+            // the call was already recorded by the `mock_wrap` mock calling the original
+            if !mock_store.is_bypassed(id) {
+                mock_store.record(id, || Call::new(self.name, args(), mocked));
+            }
         });
     }
+
+    pub fn call_mock<I: CallArgs<O>, O>(self, mock: FoundMock, input: I) -> O {
+        input.call_mock(self.id, mock)
+    }
+
+    /// Answer a call of an async function, with a future to await if the mock returns one.
+    pub fn call_mock_async<I: CallArgs<O>, O>(self, mock: FoundMock, input: I) -> MockAnswer<O> {
+        input.call_mock_async(self.id, mock)
+    }
+
+    /// Panic if the real body is about to run in strict mode without being allowed.
+    pub fn check_real_call(self) {
+        let id = self.id;
+
+        if !MOCK_STORE
+            .with(|mock_store| mock_store.take_bypass(id) || mock_store.is_real_allowed(id))
+        {
+            panic!(
+                "`{}` ran its real implementation in strict mode; mock it, or allow it with `allow_real`",
+                self.name
+            );
+        }
+    }
 }
 
-impl<O, F: Fn() -> Fut, Fut: Future<Output = O>> CallMock<(), O, Fut> for F {
-    fn call_mock(&self, _: ()) -> O {
-        let id = <Self as CallMock<(), O, Fut>>::get_mock_id(self);
+/// References to the arguments of a call, as a tuple, from which the arguments' types are inferred.
+#[doc(hidden)]
+pub trait ArgRefs<'a>: Copy {
+    type Args: Args<Refs<'a> = Self> + 'a;
+}
 
-        if let Some(MockReturn(with, repeat)) = MOCK_STORE.with(|mock_store| mock_store.get(id)) {
-            let with: Rc<Box<dyn FnMut() -> O + 'static>> = unsafe { transmute(with) };
-            let mut boxed = Rc::into_inner(with).expect("mock should exist");
-            let ret = boxed();
+/// Arguments of a call answered by a mock, as a tuple.
+#[doc(hidden)]
+pub trait CallArgs<O> {
+    fn call_mock(self, id: TypeId, mock: FoundMock) -> O;
 
-            if let Some(repeat) = repeat {
-                if repeat > 1 {
-                    let transmuted: Rc<Box<dyn FnMut() -> ()>> =
-                        unsafe { transmute(Rc::new(boxed)) };
-                    MOCK_STORE.with(|mock_store| {
-                        mock_store.add(
-                            <Self as CallMock<(), O, Fut>>::get_mock_id(self),
-                            MockReturn(transmuted, Some(repeat - 1)),
-                        );
-                    });
-                }
-            } else {
-                let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(boxed)) };
-                MOCK_STORE.with(|mock_store| {
-                    mock_store.add(
-                        <Self as CallMock<(), O, Fut>>::get_mock_id(self),
-                        MockReturn(transmuted, None),
-                    );
-                });
+    fn call_mock_async(self, id: TypeId, mock: FoundMock) -> MockAnswer<O>;
+}
+
+#[doc(hidden)]
+pub struct NotFuture;
+
+/// Future boxed by an async mock closure.
+///
+/// It is `'static`, so the functions returning `impl Future` can return it
+/// whichever lifetimes of their arguments the returned type captures.
+type MockFuture<O> = Pin<Box<dyn Future<Output = O> + Send>>;
+
+/// Answer of a mock for an async function, awaited by the function after the mock's latency.
+#[doc(hidden)]
+pub struct MockAnswer<O> {
+    wait: Wait,
+    answer: Answer<O>,
+}
+
+enum Answer<O> {
+    Ready(Option<O>),
+    Pending(MockFuture<O>),
+}
+
+impl<O> MockAnswer<O> {
+    fn ready(output: O) -> Self {
+        Self {
+            wait: Wait::new(Latency::default()),
+            answer: Answer::Ready(Some(output)),
+        }
+    }
+
+    fn pending(future: MockFuture<O>) -> Self {
+        Self {
+            wait: Wait::new(Latency::default()),
+            answer: Answer::Pending(future),
+        }
+    }
+
+    /// Answer once `latency` is waited out.
+    fn after(self, latency: Latency) -> Self {
+        Self {
+            wait: Wait::new(latency),
+            ..self
+        }
+    }
+}
+
+// the output is never pinned
+impl<O> Unpin for MockAnswer<O> {}
+
+impl<O> Future for MockAnswer<O> {
+    type Output = O;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<O> {
+        let this = self.get_mut();
+
+        if Pin::new(&mut this.wait).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        match &mut this.answer {
+            Answer::Ready(output) => {
+                Poll::Ready(output.take().expect("`MockAnswer` polled after completion"))
             }
+            Answer::Pending(future) => future.as_mut().poll(cx),
+        }
+    }
+}
+
+/// Bound making the `impl Future` of an async function mocked with `#[mock(eager)]` capture a lifetime, as `async fn` does.
+#[doc(hidden)]
+pub trait Captures<T: ?Sized> {}
+
+impl<T: ?Sized, U: ?Sized> Captures<T> for U {}
+
+/// Future of a call of a mocked async function, counted as running while it is polled.
+#[doc(hidden)]
+pub struct InCall<F> {
+    id: Option<TypeId>,
+    future: F,
+}
+
+impl<F: Future> Future for InCall<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // SAFETY: the future is never moved out of `self`, nor is it given out unpinned
+        let (id, future) = unsafe {
+            let this = self.get_unchecked_mut();
+            (this.id, Pin::new_unchecked(&mut this.future))
+        };
+
+        let _call = id.map(|id| {
+            MOCK_STORE.with(|mock_store| mock_store.enter(id));
+            CallGuard(id)
+        });
 
-            ret
-        } else {
-            panic!("mock should exist")
+        future.poll(cx)
+    }
+}
+
+/// Future returned by a mocked function returning `impl Future`: the mock's answer, or the real body's future.
+#[doc(hidden)]
+pub enum MockedFuture<O, F> {
+    Mocked(InCall<MockAnswer<O>>),
+    Real(Pin<Box<F>>),
+}
+
+impl<O, F: Future<Output = O>> Future for MockedFuture<O, F> {
+    type Output = O;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<O> {
+        match self.get_mut() {
+            MockedFuture::Mocked(answer) => Pin::new(answer).poll(cx),
+            MockedFuture::Real(future) => future.as_mut().poll(cx),
         }
     }
 }
 
+/// Guard of a running call, created by `enter_call`.
+#[doc(hidden)]
+pub struct CallGuard(TypeId);
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        // the store is already gone if the thread is shutting down
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.exit(self.0));
+    }
+}
+
+/// Makes the next call of a function run its real body, for `mock_wrap` to call the original.
+///
+/// The call may not take the flag, e.g. if `clear_mocks` made it skip the store, so it is reset on drop.
+struct Bypass;
+
+impl Bypass {
+    fn new(id: TypeId) -> Self {
+        MOCK_STORE.with(|mock_store| mock_store.bypass(id));
+
+        Self
+    }
+}
+
+impl Drop for Bypass {
+    fn drop(&mut self) {
+        let _ = MOCK_STORE.try_with(|mock_store| mock_store.end_bypass());
+    }
+}
+
 #[rustfmt::skip]
 macro_rules! all_the_tuples {
     ($name:ident) => {
+        $name!();
         $name!(T1);
         $name!(T1, T2);
         $name!(T1, T2, T3);
@@ -351,132 +1839,177 @@ macro_rules! all_the_tuples {
     };
 }
 
+macro_rules! impl_mock_fn {
+    ($($T:ident),*) => {
+        impl<$($T),*> Args for ($($T,)*) {
+            type Refs<'a> = ($(&'a $T,)*) where Self: 'a;
+        }
+
+        impl<$($T,)* P: FnMut($(&$T),*) -> bool> Predicate<($($T,)*)> for P {
+            #[allow(non_snake_case)]
+            fn matches(&mut self, ($($T,)*): ($(&$T,)*)) -> bool {
+                self($($T),*)
+            }
+        }
+
+        impl<$($T,)* R, F: Fn($($T),*) -> R> MockFn<($($T,)*)> for F {}
+
+        impl<'a, $($T),*> ArgRefs<'a> for ($(&'a $T,)*) {
+            type Args = ($($T,)*);
+        }
+
+        impl<$($T,)* O> CallArgs<O> for ($($T,)*) {
+            #[allow(non_snake_case)]
+            fn call_mock(self, id: TypeId, mock: FoundMock) -> O {
+                let ($($T,)*) = self;
+
+                match mock {
+                    FoundMock::Local(mock) => call_mock::<dyn FnMut($($T),*) -> O, O>(id, mock.take(), |with| with($($T),*)),
+                    FoundMock::Shared(mock) if mock.is_concurrent() => shared::call_concurrent::<dyn Fn($($T),*) -> O + Send + Sync, O>(mock, |with| with($($T),*)),
+                    FoundMock::Shared(mock) => shared::call_mock::<dyn FnMut($($T),*) -> O + Send, O>(mock, |with| with($($T),*)),
+                }
+            }
+
+            #[allow(non_snake_case)]
+            fn call_mock_async(self, id: TypeId, mock: FoundMock) -> MockAnswer<O> {
+                let latency = mock.latency();
+                let answer = match mock {
+                    FoundMock::Local(mock) if mock.awaits => {
+                        let ($($T,)*) = self;
+
+                        MockAnswer::pending(call_mock::<dyn FnMut($($T),*) -> MockFuture<O>, _>(id, mock.take(), |with| with($($T),*)))
+                    }
+                    mock => MockAnswer::ready(self.call_mock(id, mock)),
+                };
+
+                answer.after(latency)
+            }
+        }
+    }
+}
+all_the_tuples!(impl_mock_fn);
+
 macro_rules! impl_mock_call {
     ($($T:ident),*) => {
-        impl<$($T),*, O, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> O> MockCall<($($T,)*), O, W, NotFuture>
+        impl<$($T,)* O, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> O> MockCall<($($T,)*), O, W, NotFuture>
             for F
         {
-            fn mock_once(&self, f: W) {
-                self.mock_repeat(Some(1), f)
+            fn erase_mock(&self, with: W) -> Erased {
+                let with: Box<dyn FnMut($($T),*) -> O> = Box::new(with);
+
+                Erased::new(with)
             }
+        }
+
+        impl<$($T,)* O, P: FnMut($(&$T),*) -> bool + 'static, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> O> MockWhen<($($T,)*), O, P, W, NotFuture>
+            for F {}
 
-            fn mock_repeat(&self, repeat: Option<usize>, with: W) {
-                let erased: Box<dyn FnMut($($T),*) -> O + 'static> = Box::new(with);
-                let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(erased)) };
+        impl<$($T,)* O, W: FnMut($($T),*) -> O + Send + 'static, F: Fn($($T),*) -> O> MockShared<($($T,)*), O, W, NotFuture>
+            for F
+        {
+            fn erase_shared(&self, with: W) -> SharedErased {
+                let with: Box<dyn FnMut($($T),*) -> O + Send> = Box::new(with);
 
-                MOCK_STORE.with(|mock_store| {
-                    mock_store.add(
-                        <Self as CallMock<($($T,)*), O, NotFuture>>::get_mock_id(self),
-                        MockReturn(transmuted, repeat),
-                    );
-                });
+                SharedErased::new(with)
             }
         }
 
-        impl<$($T),*, O, F: Fn($($T),*) -> O> CallMock<($($T,)*), O, NotFuture>
+        impl<$($T,)* O, W: FnMut(&dyn Fn($($T),*) -> O, $($T),*) -> O + 'static, F: Fn($($T),*) -> O + Copy + 'static> MockWrap<($($T,)*), O, W>
             for F
         {
             #[allow(non_snake_case)]
-            fn call_mock(&self, ($($T,)*): ($($T,)*)) -> O {
+            fn mock_wrap(&self, mut with: W) -> MockHandle {
                 let id = <Self as CallMock<($($T,)*), O, NotFuture>>::get_mock_id(self);
+                let f = *self;
+                let original = move |$($T),*| {
+                    let _bypass = Bypass::new(id);
+                    f($($T),*)
+                };
 
-                if let Some(MockReturn(with, repeat)) = MOCK_STORE.with(|mock_store| mock_store.get(id)) {
-                    let with: Rc<Box<dyn FnMut($($T),*) -> O + 'static>> = unsafe { transmute(with) };
-                    let mut boxed = Rc::into_inner(with).expect("mock should exist");
-                    let ret = boxed($($T),*);
-
-                    if let Some(repeat) = repeat {
-                        if repeat > 1 {
-                            let transmuted: Rc<Box<dyn FnMut() -> ()>> =
-                                unsafe { transmute(Rc::new(boxed)) };
-                            MOCK_STORE.with(|mock_store| {
-                                mock_store.add(
-                                    <Self as CallMock<($($T,)*), O, NotFuture>>::get_mock_id(self),
-                                    MockReturn(transmuted, Some(repeat - 1)),
-                                );
-                            });
-                        }
-                    } else {
-                        let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(boxed)) };
-                        MOCK_STORE.with(|mock_store| {
-                            mock_store.add(
-                                <Self as CallMock<($($T,)*), O, NotFuture>>::get_mock_id(self),
-                                MockReturn(transmuted, None),
-                            );
-                        });
-                    }
+                self.mock_repeat(None, move |$($T),*| with(&original, $($T),*))
+            }
+        }
 
-                    ret
-                } else {
-                    panic!("mock should exist")
-                }
+        impl<$($T,)* O, F: Fn($($T),*) -> O> MockGated<($($T,)*), O>
+            for F
+        {
+            fn erase_gated(&self, gate: Gate<O>) -> SharedErased
+            where
+                O: Send + 'static,
+            {
+                let with: Box<dyn Fn($($T),*) -> O + Send + Sync> = Box::new(move |$(_: $T),*| gate.pass());
+
+                SharedErased::concurrent(with)
             }
         }
+
+        impl<$($T,)* O, F: Fn($($T),*) -> O> CallMock<($($T,)*), O, NotFuture>
+            for F {}
     }
 }
 all_the_tuples!(impl_mock_call);
 
 macro_rules! impl_mock_async_call {
     ($($T:ident),*) => {
-        impl<$($T),*, O, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockCall<($($T,)*), O, W, Fut>
+        impl<$($T,)* O, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockCall<($($T,)*), O, W, Fut>
             for F
         {
-            fn mock_once(&self, f: W) {
-                self.mock_repeat(Some(1), f)
+            fn erase_mock(&self, with: W) -> Erased {
+                let with: Box<dyn FnMut($($T),*) -> O> = Box::new(with);
+
+                Erased::new(with)
             }
+        }
+
+        impl<$($T,)* O, P: FnMut($(&$T),*) -> bool + 'static, W: FnMut($($T),*) -> O + 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockWhen<($($T,)*), O, P, W, Fut>
+            for F {}
 
-            fn mock_repeat(&self, repeat: Option<usize>, with: W) {
-                let erased: Box<dyn FnMut($($T),*) -> O + 'static> = Box::new(with);
-                let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(erased)) };
+        impl<$($T,)* O, W: FnMut($($T),*) -> O + Send + 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockShared<($($T,)*), O, W, Fut>
+            for F
+        {
+            fn erase_shared(&self, with: W) -> SharedErased {
+                let with: Box<dyn FnMut($($T),*) -> O + Send> = Box::new(with);
 
-                MOCK_STORE.with(|mock_store| {
-                    mock_store.add(
-                        <Self as CallMock<($($T,)*), O, Fut>>::get_mock_id(self),
-                        MockReturn(transmuted, repeat),
-                    );
-                });
+                SharedErased::new(with)
             }
         }
 
-        impl<$($T),*, O, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> CallMock<($($T,)*), O, Fut>
+        impl<$($T,)* O, W: FnMut($($T),*) -> WFut + 'static, WFut: Future<Output = O> + Send + 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockAsync<($($T,)*), O, W, Fut, WFut>
             for F
         {
             #[allow(non_snake_case)]
-            fn call_mock(&self, ($($T,)*): ($($T,)*)) -> O {
-                let id = <Self as CallMock<($($T,)*), O, Fut>>::get_mock_id(self);
-
-                if let Some(MockReturn(with, repeat)) = MOCK_STORE.with(|mock_store| mock_store.get(id)) {
-                    let with: Rc<Box<dyn FnMut($($T),*) -> O + 'static>> = unsafe { transmute(with) };
-                    let mut boxed = Rc::into_inner(with).expect("mock should exist");
-                    let ret = boxed($($T),*);
-
-                    if let Some(repeat) = repeat {
-                        if repeat > 1 {
-                            let transmuted: Rc<Box<dyn FnMut() -> ()>> =
-                                unsafe { transmute(Rc::new(boxed)) };
-                            MOCK_STORE.with(|mock_store| {
-                                mock_store.add(
-                                    <Self as CallMock<($($T,)*), O, Fut>>::get_mock_id(self),
-                                    MockReturn(transmuted, Some(repeat - 1)),
-                                );
-                            });
-                        }
-                    } else {
-                        let transmuted: Rc<Box<dyn FnMut() -> ()>> = unsafe { transmute(Rc::new(boxed)) };
-                        MOCK_STORE.with(|mock_store| {
-                            mock_store.add(
-                                <Self as CallMock<($($T,)*), O, Fut>>::get_mock_id(self),
-                                MockReturn(transmuted, None),
-                            );
-                        });
-                    }
+            fn erase_async(&self, mut with: W) -> Erased {
+                let with: Box<dyn FnMut($($T),*) -> MockFuture<O>> = Box::new(move |$($T),*| Box::pin(with($($T),*)));
 
-                    ret
-                } else {
-                    panic!("mock should exist")
-                }
+                Erased::new(with)
+            }
+        }
+
+        impl<$($T,)* O: 'static, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockPending<($($T,)*), O, Fut>
+            for F
+        {
+            fn erase_pending(&self) -> Erased {
+                let with: Box<dyn FnMut($($T),*) -> MockFuture<O>> = Box::new(|$(_: $T),*| Box::pin(std::future::pending()));
+
+                Erased::new(with)
+            }
+        }
+
+        impl<$($T,)* O, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> MockDeferred<($($T,)*), O, Fut>
+            for F
+        {
+            fn erase_deferred(&self, deferred: Deferred<O>) -> Erased
+            where
+                O: Send + 'static,
+            {
+                let with: Box<dyn FnMut($($T),*) -> MockFuture<O>> = Box::new(move |$(_: $T),*| Box::pin(deferred.future()));
+
+                Erased::new(with)
             }
         }
+
+        impl<$($T,)* O, F: Fn($($T),*) -> Fut, Fut: Future<Output = O>> CallMock<($($T,)*), O, Fut>
+            for F {}
     }
 }
 all_the_tuples!(impl_mock_async_call);
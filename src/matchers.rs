@@ -0,0 +1,373 @@
+//! Matchers for the arguments of mocked functions.
+//!
+//! A tuple with one matcher per argument can be used with `mock_matching`:
+//!
+//! ```rust
+//! # #[mockem::mock]
+//! # fn get(key: &str, version: u32) -> String { format!("{key}@{version}") }
+//! use mockem::{matchers::*, MockCall};
+//!
+//! get.mock_matching((starts_with("user:"), gt(1)), |_, _| "mocked".to_owned());
+//!
+//! assert_eq!(get("user:1", 2), "mocked");
+//! assert_eq!(get("user:1", 1), "user:1@1");
+//! ```
+//!
+//! Matchers describe what they expect, so mismatches can be reported:
+//!
+//! ```rust
+//! use mockem::matchers::*;
+//!
+//! let matcher = (any(), contains("user"));
+//!
+//! assert_eq!(
+//!     ArgsMatcher::<(u32, &str)>::mismatch(&matcher, (&1, &"admin")).unwrap(),
+//!     "expected arg #2 to contain \"user\" but got \"admin\"",
+//! );
+//! ```
+
+use std::fmt::Debug;
+
+use crate::{Args, Predicate};
+
+/// Matches a single argument of type `T`.
+pub trait Matcher<T: ?Sized> {
+    fn matches(&self, value: &T) -> bool;
+
+    /// What the matcher expects, e.g. `to equal 3`.
+    fn describe(&self) -> String;
+
+    /// How `value` is shown in mismatch reports, if it can be shown.
+    fn describe_value(&self, _value: &T) -> Option<String> {
+        None
+    }
+}
+
+/// Matches all arguments of a function; implemented for tuples with one `Matcher` per argument.
+pub trait ArgsMatcher<I: Args> {
+    fn matches(&self, args: I::Refs<'_>) -> bool;
+
+    /// Describe the first argument that doesn't match,
+    /// e.g. `expected arg #2 to contain "user" but got "admin"`.
+    fn mismatch(&self, args: I::Refs<'_>) -> Option<String>;
+
+    /// What the matchers expect, e.g. `(to be anything, to contain "user")`.
+    fn describe(&self) -> String;
+}
+
+/// Adapts `ArgsMatcher` to the predicates stored with mocks.
+pub(crate) struct Matching<M>(pub(crate) M);
+
+impl<I: Args, M: ArgsMatcher<I>> Predicate<I> for Matching<M> {
+    fn matches(&mut self, args: I::Refs<'_>) -> bool {
+        self.0.matches(args)
+    }
+
+    fn mismatch(&mut self, args: I::Refs<'_>) -> Option<String> {
+        self.0.mismatch(args)
+    }
+}
+
+fn mismatch<T: ?Sized>(index: usize, matcher: &dyn Matcher<T>, value: &T) -> String {
+    match matcher.describe_value(value) {
+        Some(value) => format!(
+            "expected arg #{index} {} but got {value}",
+            matcher.describe()
+        ),
+        None => format!("expected arg #{index} {}", matcher.describe()),
+    }
+}
+
+macro_rules! impl_args_matcher {
+    ($($M:ident $T:ident $i:tt),*) => {
+        impl<$($T,)* $($M: Matcher<$T>),*> ArgsMatcher<($($T,)*)> for ($($M,)*) {
+            #[allow(non_snake_case)]
+            fn matches(&self, ($($T,)*): ($(&$T,)*)) -> bool {
+                $(self.$i.matches($T))&&*
+            }
+
+            #[allow(non_snake_case)]
+            fn mismatch(&self, ($($T,)*): ($(&$T,)*)) -> Option<String> {
+                $(
+                    if !self.$i.matches($T) {
+                        return Some(mismatch($i + 1, &self.$i, $T));
+                    }
+                )*
+
+                None
+            }
+
+            fn describe(&self) -> String {
+                let described: Vec<String> = vec![$(self.$i.describe()),*];
+
+                format!("({})", described.join(", "))
+            }
+        }
+
+        impl<T: ?Sized, $($M: Matcher<T>),*> MatcherList<T> for ($($M,)*) {
+            fn each(&self) -> Vec<&dyn Matcher<T>> {
+                vec![$(&self.$i),*]
+            }
+        }
+    }
+}
+
+#[rustfmt::skip]
+macro_rules! all_the_matcher_tuples {
+    ($name:ident) => {
+        $name!(M0 T0 0);
+        $name!(M0 T0 0, M1 T1 1);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6, M7 T7 7);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6, M7 T7 7, M8 T8 8);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6, M7 T7 7, M8 T8 8, M9 T9 9);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6, M7 T7 7, M8 T8 8, M9 T9 9, M10 T10 10);
+        $name!(M0 T0 0, M1 T1 1, M2 T2 2, M3 T3 3, M4 T4 4, M5 T5 5, M6 T6 6, M7 T7 7, M8 T8 8, M9 T9 9, M10 T10 10, M11 T11 11);
+    };
+}
+all_the_matcher_tuples!(impl_args_matcher);
+
+/// Matches anything.
+pub fn any() -> Any {
+    Any
+}
+
+#[doc(hidden)]
+pub struct Any;
+
+impl<T: ?Sized> Matcher<T> for Any {
+    fn matches(&self, _: &T) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        "to be anything".to_owned()
+    }
+}
+
+macro_rules! comparison {
+    ($(#[$doc:meta])* $fn:ident, $Name:ident, $Trait:ident, $op:tt, $desc:literal) => {
+        $(#[$doc])*
+        pub fn $fn<X>(expected: X) -> $Name<X> {
+            $Name(expected)
+        }
+
+        #[doc(hidden)]
+        pub struct $Name<X>(X);
+
+        impl<T: $Trait<X> + Debug, X: Debug> Matcher<T> for $Name<X> {
+            fn matches(&self, value: &T) -> bool {
+                *value $op self.0
+            }
+
+            fn describe(&self) -> String {
+                format!(concat!($desc, " {:?}"), self.0)
+            }
+
+            fn describe_value(&self, value: &T) -> Option<String> {
+                Some(format!("{value:?}"))
+            }
+        }
+    };
+}
+
+comparison!(
+    /// Matches values equal to `expected`.
+    eq, Eq, PartialEq, ==, "to equal"
+);
+comparison!(
+    /// Matches values not equal to `expected`.
+    ne, Ne, PartialEq, !=, "to not equal"
+);
+comparison!(
+    /// Matches values less than `expected`.
+    lt, Lt, PartialOrd, <, "to be less than"
+);
+comparison!(
+    /// Matches values less than or equal to `expected`.
+    le, Le, PartialOrd, <=, "to be at most"
+);
+comparison!(
+    /// Matches values greater than `expected`.
+    gt, Gt, PartialOrd, >, "to be greater than"
+);
+comparison!(
+    /// Matches values greater than or equal to `expected`.
+    ge, Ge, PartialOrd, >=, "to be at least"
+);
+
+/// Matches values for which `f` returns true.
+///
+/// `description` completes "expected arg #1 to ...", e.g. `predicate("be even", |n| n % 2 == 0)`.
+pub fn predicate<F>(description: &str, f: F) -> FnMatcher<F> {
+    FnMatcher(format!("to {description}"), f)
+}
+
+#[doc(hidden)]
+pub struct FnMatcher<F>(String, F);
+
+impl<T: ?Sized, F: Fn(&T) -> bool> Matcher<T> for FnMatcher<F> {
+    fn matches(&self, value: &T) -> bool {
+        (self.1)(value)
+    }
+
+    fn describe(&self) -> String {
+        self.0.clone()
+    }
+}
+
+macro_rules! string_matcher {
+    ($(#[$doc:meta])* $fn:ident, $Name:ident, $desc:literal, |$value:ident, $expected:ident| $matches:expr) => {
+        $(#[$doc])*
+        pub fn $fn(expected: impl Into<String>) -> $Name {
+            $Name(expected.into())
+        }
+
+        #[doc(hidden)]
+        pub struct $Name(String);
+
+        impl<T: AsRef<str> + ?Sized> Matcher<T> for $Name {
+            fn matches(&self, value: &T) -> bool {
+                let $value = value.as_ref();
+                let $expected = self.0.as_str();
+
+                $matches
+            }
+
+            fn describe(&self) -> String {
+                format!(concat!($desc, " {:?}"), self.0)
+            }
+
+            fn describe_value(&self, value: &T) -> Option<String> {
+                Some(format!("{:?}", value.as_ref()))
+            }
+        }
+    };
+}
+
+string_matcher!(
+    /// Matches strings containing `expected`.
+    contains, Contains, "to contain", |value, expected| value.contains(expected)
+);
+string_matcher!(
+    /// Matches strings starting with `expected`.
+    starts_with, StartsWith, "to start with", |value, expected| value.starts_with(expected)
+);
+string_matcher!(
+    /// Matches strings ending with `expected`.
+    ends_with, EndsWith, "to end with", |value, expected| value.ends_with(expected)
+);
+string_matcher!(
+    /// Matches strings against a wildcard pattern, where `*` matches any sequence of characters
+    /// and `?` matches any single character, e.g. `pattern("user_*@*.com")`.
+    pattern, Pattern, "to match", |value, expected| wildcard_match(expected, value)
+);
+
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let value = value.chars().collect::<Vec<_>>();
+
+    // matched[j]: whether the pattern so far matches the first j characters of the value
+    let mut matched = vec![false; value.len() + 1];
+    matched[0] = true;
+
+    for p in pattern {
+        let mut next = vec![false; value.len() + 1];
+
+        for j in 0..=value.len() {
+            next[j] = match p {
+                '*' => matched[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matched[j - 1],
+                c => j > 0 && matched[j - 1] && value[j - 1] == c,
+            };
+        }
+
+        matched = next;
+    }
+
+    matched[value.len()]
+}
+
+/// Matches values that don't match `matcher`.
+pub fn not<M>(matcher: M) -> Not<M> {
+    Not(matcher)
+}
+
+#[doc(hidden)]
+pub struct Not<M>(M);
+
+impl<T: ?Sized, M: Matcher<T>> Matcher<T> for Not<M> {
+    fn matches(&self, value: &T) -> bool {
+        !self.0.matches(value)
+    }
+
+    fn describe(&self) -> String {
+        format!("not {}", self.0.describe())
+    }
+
+    fn describe_value(&self, value: &T) -> Option<String> {
+        self.0.describe_value(value)
+    }
+}
+
+/// A tuple of matchers for the same type, used by `all_of` and `any_of`.
+#[doc(hidden)]
+pub trait MatcherList<T: ?Sized> {
+    fn each(&self) -> Vec<&dyn Matcher<T>>;
+}
+
+/// Matches values matching every matcher in the tuple, e.g. `all_of((starts_with("a"), ends_with("z")))`.
+pub fn all_of<L>(matchers: L) -> AllOf<L> {
+    AllOf(matchers)
+}
+
+#[doc(hidden)]
+pub struct AllOf<L>(L);
+
+impl<T: ?Sized, L: MatcherList<T>> Matcher<T> for AllOf<L> {
+    fn matches(&self, value: &T) -> bool {
+        self.0.each().iter().all(|m| m.matches(value))
+    }
+
+    fn describe(&self) -> String {
+        describe_list(self.0.each(), " and ")
+    }
+
+    fn describe_value(&self, value: &T) -> Option<String> {
+        self.0.each().iter().find_map(|m| m.describe_value(value))
+    }
+}
+
+/// Matches values matching at least one matcher in the tuple, e.g. `any_of((eq(1), eq(2)))`.
+pub fn any_of<L>(matchers: L) -> AnyOf<L> {
+    AnyOf(matchers)
+}
+
+#[doc(hidden)]
+pub struct AnyOf<L>(L);
+
+impl<T: ?Sized, L: MatcherList<T>> Matcher<T> for AnyOf<L> {
+    fn matches(&self, value: &T) -> bool {
+        self.0.each().iter().any(|m| m.matches(value))
+    }
+
+    fn describe(&self) -> String {
+        describe_list(self.0.each(), " or ")
+    }
+
+    fn describe_value(&self, value: &T) -> Option<String> {
+        self.0.each().iter().find_map(|m| m.describe_value(value))
+    }
+}
+
+fn describe_list<T: ?Sized>(matchers: Vec<&dyn Matcher<T>>, separator: &str) -> String {
+    matchers
+        .iter()
+        .map(|m| m.describe())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
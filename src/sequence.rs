@@ -0,0 +1,145 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+    rc::Rc,
+};
+
+/// Order in which mocks and expectations of different functions must answer calls.
+///
+/// Mocks join with `MockHandle::in_sequence`, and expectations with `ExpectationBuilder::in_sequence`,
+/// in the order their calls are expected.
+/// Each of them must be used up (or, for expectations, called the least number of times expected)
+/// before the next one answers a call; otherwise the call panics with the expected and actual order.
+///
+/// Only calls answered by the joined mocks are ordered; calls to the real function are not.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn connect() {}
+/// # #[mockem::mock]
+/// # fn send() {}
+/// use mockem::{MockCall, Sequence};
+///
+/// let seq = Sequence::new();
+/// connect.mock_once(|| ()).in_sequence(&seq);
+/// send.mock_repeat(Some(2), || ()).in_sequence(&seq);
+///
+/// connect();
+/// send();
+/// send();
+///
+/// seq.verify();
+/// ```
+#[derive(Clone, Default)]
+pub struct Sequence(Rc<SequenceState>);
+
+#[derive(Default)]
+struct SequenceState {
+    steps: RefCell<Vec<Step>>,
+    // names of the calls answered in the sequence, in order
+    actual: RefCell<Vec<&'static str>>,
+    // index of the step that answered last
+    position: Cell<usize>,
+}
+
+struct Step {
+    name: &'static str,
+    // calls needed before the next step may answer
+    required: usize,
+    calls: usize,
+}
+
+/// A mock's place in a `Sequence`.
+pub(crate) struct SequenceStep {
+    sequence: Sequence,
+    index: usize,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step at the end of the sequence.
+    pub(crate) fn join(&self, name: &'static str, required: usize) -> SequenceStep {
+        let mut steps = self.0.steps.borrow_mut();
+
+        steps.push(Step {
+            name,
+            required,
+            calls: 0,
+        });
+
+        SequenceStep {
+            sequence: self.clone(),
+            index: steps.len() - 1,
+        }
+    }
+
+    /// Panic if any mock or expectation in the sequence didn't answer all the calls it needed.
+    pub fn verify(&self) {
+        let missing = self
+            .0
+            .steps
+            .borrow()
+            .iter()
+            .find(|step| step.calls < step.required)
+            .map(|step| step.name);
+
+        if let Some(missing) = missing {
+            panic!(
+                "sequence is incomplete, `{missing}` was not called{}",
+                self.order()
+            );
+        }
+    }
+
+    fn order(&self) -> String {
+        let mut order = String::from("\n    expected order: ");
+
+        for (i, step) in self.0.steps.borrow().iter().enumerate() {
+            if i > 0 {
+                order.push_str(", ");
+            }
+            let _ = write!(order, "{}", step.name);
+        }
+
+        order.push_str("\n    actual order: ");
+        order.push_str(&self.0.actual.borrow().join(", "));
+        order
+    }
+}
+
+impl SequenceStep {
+    /// Count a call answered by this step.
+    ///
+    /// Panics if an earlier step isn't done, or a later step already answered.
+    pub(crate) fn call(&self) {
+        let state = &self.sequence.0;
+        let mut steps = state.steps.borrow_mut();
+        let name = steps[self.index].name;
+        let position = state.position.get();
+
+        state.actual.borrow_mut().push(name);
+
+        let error = if self.index < position {
+            Some(format!(
+                "`{name}` was called after `{}`",
+                steps[position].name
+            ))
+        } else {
+            steps[..self.index]
+                .iter()
+                .find(|step| step.calls < step.required)
+                .map(|before| format!("`{name}` was called before `{}`", before.name))
+        };
+
+        if let Some(error) = error {
+            drop(steps);
+            panic!("{error}{}", self.sequence.order());
+        }
+
+        steps[self.index].calls += 1;
+        state.position.set(self.index);
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    any::{type_name, TypeId},
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::store::{erased_id, ErasedBox};
+
+/// A boxed `Send` closure with its type erased, shared between threads.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct SharedErased {
+    erased: Arc<SendBox>,
+    // whether calls run the closure at the same time, instead of taking turns
+    concurrent: bool,
+}
+
+struct SendBox(ErasedBox);
+
+// SAFETY: only created from `Send` closures behind a `Mutex`, or `Send + Sync` closures
+unsafe impl Send for SendBox {}
+unsafe impl Sync for SendBox {}
+
+impl SharedErased {
+    pub(crate) fn new<C: ?Sized + Send>(value: Box<C>) -> Self {
+        Self {
+            erased: Arc::new(SendBox(ErasedBox::new(Mutex::new(value)))),
+            concurrent: false,
+        }
+    }
+
+    /// A closure that calls from several threads run at the same time.
+    pub(crate) fn concurrent<C: ?Sized + Send + Sync>(value: Box<C>) -> Self {
+        Self {
+            erased: Arc::new(SendBox(ErasedBox::new(value))),
+            concurrent: true,
+        }
+    }
+
+    fn erased(&self) -> &ErasedBox {
+        let SendBox(erased) = &*self.erased;
+        erased
+    }
+}
+
+/// A shared mock found for a call.
+#[doc(hidden)]
+pub struct SharedReturn {
+    key: u64,
+    name: &'static str,
+    with: SharedErased,
+}
+
+impl SharedReturn {
+    pub(crate) fn is_concurrent(&self) -> bool {
+        self.with.concurrent
+    }
+}
+
+/// What a shared mock's closure takes and returns, checked against calls before they count against the mock.
+#[derive(Clone, Copy)]
+pub(crate) struct Signature {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl Signature {
+    pub(crate) fn of<I, O>() -> Self {
+        Self {
+            id: erased_id::<fn(I) -> O>(),
+            name: type_name::<fn(I) -> O>(),
+        }
+    }
+}
+
+struct SharedMock {
+    // unique to the mock, to tell which mocks are running on a thread
+    key: u64,
+    name: &'static str,
+    signature: Signature,
+    with: SharedErased,
+    // `None` means the mock answers indefinitely
+    remaining: Option<usize>,
+}
+
+/// Shared mocks of a scope, seen by the threads and tasks in it.
+pub(crate) struct Scope {
+    id: u64,
+    mocks: Mutex<ScopeMocks>,
+    // number of queued mocks, so calls skip the lock when there are none
+    queued: AtomicUsize,
+}
+
+#[derive(Default)]
+struct ScopeMocks {
+    // (fn type_id) -> mocks, oldest first
+    mocks: HashMap<TypeId, VecDeque<SharedMock>>,
+    closed: bool,
+}
+
+impl Scope {
+    fn mocks(&self) -> MutexGuard<'_, ScopeMocks> {
+        // a test panicking while holding the lock leaves the mocks consistent
+        self.mocks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Scope {
+    /// Describe the mocks with calls left to answer, other than indefinite ones, as `kind` calls.
+    fn unused(&self, kind: &str) -> Vec<String> {
+        self.mocks()
+            .mocks
+            .values()
+            .flatten()
+            .filter_map(|m| {
+                let calls = match m.remaining? {
+                    1 => format!("1 {kind} call was"),
+                    unused => format!("{unused} {kind} calls were"),
+                };
+
+                Some(format!("{calls} never made to `{}`", m.name))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+static NEXT_KEY: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // scope the thread is in, if any
+    static SCOPE: RefCell<Option<Arc<Scope>>> = const { RefCell::new(None) };
+    // keys of the shared mocks running on this thread
+    static RUNNING: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    // scope of the mocks queued on this thread with `MockSend`, shared with the threads its context is entered on
+    static CARRIED: RefCell<Option<Arc<Scope>>> = const { RefCell::new(None) };
+}
+
+/// Scope of shared mocks, which are removed when it is dropped.
+///
+/// Only the threads and tasks in the scope see its mocks: the thread that created it,
+/// and those it passes its context on to, e.g. with `mockem::thread::spawn`.
+/// Scopes don't see each other's mocks, and threads outside any scope see none, so tests using them run in parallel.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn foo() -> u8 { 0 }
+/// use mockem::MockShared;
+///
+/// let scope = mockem::shared_scope();
+/// foo.mock_shared(None, || 1);
+///
+/// assert_eq!(mockem::thread::spawn(foo).join().unwrap(), 1);
+/// // threads started otherwise aren't in the scope
+/// assert_eq!(std::thread::spawn(foo).join().unwrap(), 0);
+///
+/// drop(scope);
+/// assert_eq!(mockem::thread::spawn(foo).join().unwrap(), 0);
+/// ```
+#[must_use = "shared mocks are removed as soon as the scope is dropped"]
+pub struct SharedScope(Arc<Scope>);
+
+/// Create a scope for shared mocks, and put the current thread in it.
+///
+/// `#[mockem::test(shared)]` runs the whole test in a shared scope.
+pub fn shared_scope() -> SharedScope {
+    assert!(
+        SCOPE.with(|scope| scope.borrow().is_none()),
+        "the thread is already in a shared scope"
+    );
+
+    let scope = open_scope();
+    enter_scope(Some(scope.clone()));
+
+    SharedScope(scope)
+}
+
+impl SharedScope {
+    /// Describe the mocks of the scope with calls left to answer, other than indefinite ones.
+    pub(crate) fn unused(&self) -> Vec<String> {
+        self.0.unused("shared mocked")
+    }
+}
+
+impl Drop for SharedScope {
+    fn drop(&mut self) {
+        let _ = SCOPE.try_with(|scope| scope.borrow_mut().take());
+        close_scope(&self.0);
+    }
+}
+
+/// Open a new scope, which only the threads and tasks put in it see.
+pub(crate) fn open_scope() -> Arc<Scope> {
+    Arc::new(Scope {
+        id: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+        mocks: Default::default(),
+        queued: AtomicUsize::new(0),
+    })
+}
+
+/// Close a scope, removing its mocks.
+pub(crate) fn close_scope(scope: &Scope) {
+    let removed = {
+        let mut mocks = scope.mocks();
+        mocks.closed = true;
+        scope.queued.store(0, Ordering::Relaxed);
+
+        std::mem::take(&mut mocks.mocks)
+    };
+
+    // the closures are dropped without the scope locked
+    drop(removed);
+}
+
+/// Scope the current task or thread is in, if any.
+pub(crate) fn current_scope() -> Option<Arc<Scope>> {
+    #[cfg(feature = "tokio")]
+    if let Some(scope) = crate::tokio::task_scope() {
+        return Some(scope);
+    }
+
+    SCOPE.with(|scope| scope.borrow().clone())
+}
+
+/// Scope the current thread is in, if any.
+#[cfg(feature = "tokio")]
+pub(crate) fn thread_scope() -> Option<Arc<Scope>> {
+    SCOPE.with(|scope| scope.borrow().clone())
+}
+
+/// Put the current thread in `scope`, returning the scope it was in.
+pub(crate) fn enter_scope(scope: Option<Arc<Scope>>) -> Option<Arc<Scope>> {
+    SCOPE.with(|current| current.replace(scope))
+}
+
+/// Queue a shared mock in the current thread's scope.
+pub(crate) fn add(
+    id: TypeId,
+    name: &'static str,
+    signature: Signature,
+    with: SharedErased,
+    repeat: Option<usize>,
+) {
+    let scope = current_scope().unwrap_or_else(|| {
+        panic!("`mock_shared` needs a shared scope, from `mockem::shared_scope()` or `#[mockem::test(shared)]`")
+    });
+    let mut mocks = scope.mocks();
+
+    // a thread can be left in a scope by a captured `MockContext`
+    assert!(
+        !mocks.closed,
+        "`mock_shared` was called after the shared scope of the thread ended"
+    );
+
+    push(&scope, &mut mocks, id, name, signature, with, repeat);
+}
+
+/// Queue a mock in the current thread's carried scope, which its context carries into other threads.
+pub(crate) fn add_carried(
+    id: TypeId,
+    name: &'static str,
+    signature: Signature,
+    with: SharedErased,
+    repeat: Option<usize>,
+) {
+    let scope = carried_scope();
+    let mut mocks = scope.mocks();
+
+    push(&scope, &mut mocks, id, name, signature, with, repeat);
+}
+
+fn push(
+    scope: &Scope,
+    mocks: &mut ScopeMocks,
+    id: TypeId,
+    name: &'static str,
+    signature: Signature,
+    with: SharedErased,
+    repeat: Option<usize>,
+) {
+    if repeat == Some(0) {
+        return;
+    }
+
+    mocks.mocks.entry(id).or_default().push_back(SharedMock {
+        key: NEXT_KEY.fetch_add(1, Ordering::Relaxed),
+        name,
+        signature,
+        with,
+        remaining: repeat,
+    });
+
+    scope.queued.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Carried scope of the current thread, opened if it has none, or if it was cleared on another thread.
+pub(crate) fn carried_scope() -> Arc<Scope> {
+    CARRIED.with(|carried| {
+        let mut carried = carried.borrow_mut();
+
+        match &*carried {
+            Some(scope) if !scope.mocks().closed => scope.clone(),
+            _ => carried.insert(open_scope()).clone(),
+        }
+    })
+}
+
+/// Put the current thread in a carried scope, returning the one it had.
+pub(crate) fn enter_carried(scope: Option<Arc<Scope>>) -> Option<Arc<Scope>> {
+    CARRIED.with(|carried| carried.replace(scope))
+}
+
+/// Describe the mocks of the current thread's carried scope with calls left to answer, other than indefinite ones.
+pub(crate) fn carried_unused() -> Vec<String> {
+    CARRIED
+        .try_with(|carried| {
+            carried
+                .borrow()
+                .as_ref()
+                .map(|scope| scope.unused("mocked"))
+                .unwrap_or_default()
+        })
+        .unwrap_or_default()
+}
+
+/// Remove the mocks of the current thread's carried scope, on every thread it was carried to.
+pub(crate) fn clear_carried() {
+    if let Ok(Some(scope)) = CARRIED.try_with(|carried| carried.borrow_mut().take()) {
+        close_scope(&scope);
+    }
+}
+
+/// Whether the function has shared mocks in the current thread's scopes.
+pub(crate) fn is_mocked(id: TypeId) -> bool {
+    CARRIED.with(|carried| {
+        carried
+            .borrow()
+            .as_ref()
+            .is_some_and(|scope| has_mocks(scope, id))
+    }) || current_scope().is_some_and(|scope| has_mocks(&scope, id))
+}
+
+fn has_mocks(scope: &Scope, id: TypeId) -> bool {
+    if scope.queued.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+
+    let mocks = scope.mocks();
+
+    mocks.mocks.get(&id).is_some_and(|mocks| !mocks.is_empty())
+}
+
+/// Find the first shared mock of the function that isn't running on this thread, and count the call.
+///
+/// Mocks carried from the thread that queued them answer before those of the shared scope.
+/// Panics if the mock was added with another signature than the call's.
+pub(crate) fn find_mock(id: TypeId, signature: Signature) -> Option<SharedReturn> {
+    CARRIED
+        .with(|carried| {
+            carried
+                .borrow()
+                .as_ref()
+                .and_then(|scope| find_in(scope, id, signature))
+        })
+        .or_else(|| find_in(&*current_scope()?, id, signature))
+}
+
+fn find_in(scope: &Scope, id: TypeId, signature: Signature) -> Option<SharedReturn> {
+    if scope.queued.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+
+    let running = RUNNING.with(|running| running.borrow().clone());
+    let mut scope_mocks = scope.mocks();
+    let mocks = scope_mocks.mocks.get_mut(&id)?;
+    let index = mocks.iter().position(|m| !running.contains(&m.key))?;
+    let mock = &mut mocks[index];
+
+    // checked before the call is counted, so the mock isn't used up by calls it can't answer
+    if mock.signature.id != signature.id {
+        let (name, added) = (mock.name, mock.signature.name);
+        drop(scope_mocks);
+
+        panic!(
+            "shared mock of `{name}` has the wrong type: it was added as `{added}`, but the call is `{}`",
+            signature.name
+        );
+    }
+
+    let found = SharedReturn {
+        key: mock.key,
+        name: mock.name,
+        with: mock.with.clone(),
+    };
+
+    // counted before the call, so threads calling at the same time don't use up a mock twice
+    if let Some(remaining) = &mut mock.remaining {
+        *remaining -= 1;
+
+        if *remaining == 0 {
+            mocks.remove(index);
+            scope.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    Some(found)
+}
+
+/// Answer a call with a shared mock found by `find_mock`.
+///
+/// Calls from other threads wait for the closure; calls the closure makes itself skip it.
+///
+/// `C` must be the closure type the mock was added with.
+pub(crate) fn call_mock<C: ?Sized, O>(mock: SharedReturn, call: impl FnOnce(&mut C) -> O) -> O {
+    run(mock, |with: &Mutex<Box<C>>| {
+        call(&mut with.lock().unwrap_or_else(PoisonError::into_inner))
+    })
+}
+
+/// Answer a call with a concurrent shared mock found by `find_mock`, without waiting for other calls.
+///
+/// `C` must be the closure type the mock was added with.
+pub(crate) fn call_concurrent<C: ?Sized, O>(mock: SharedReturn, call: impl FnOnce(&C) -> O) -> O {
+    run::<Box<C>, O>(mock, |with| call(with))
+}
+
+/// Run a call with the mock's closure as `T`, marking the mock as running on this thread.
+fn run<T, O>(mock: SharedReturn, call: impl FnOnce(&T) -> O) -> O {
+    struct Running(u64);
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            let _ = RUNNING.try_with(|running| running.borrow_mut().retain(|&key| key != self.0));
+        }
+    }
+
+    // SAFETY: the closure is only used for this call, and mocks don't keep the arguments (see `MockCall`)
+    let with = unsafe { mock.with.erased().get::<T>() }.unwrap_or_else(|| {
+        panic!(
+            "shared mock of `{}` has the wrong type: it was added as `{}`, but the call expects `{}`",
+            mock.name,
+            mock.with.erased().type_name,
+            std::any::type_name::<T>()
+        )
+    });
+
+    RUNNING.with(|running| running.borrow_mut().push(mock.key));
+    let _running = Running(mock.key);
+
+    call(with)
+}
@@ -1,50 +1,501 @@
 use std::{
-    any::TypeId,
-    cell::RefCell,
-    collections::{HashMap, VecDeque},
+    any::{type_name, Any, TypeId},
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
+    ptr::NonNull,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
-use crate::MockReturn;
+use crate::{
+    expect::ExpectationState,
+    handle::{Layer, MockState},
+    Call,
+};
+
+/// A boxed closure with its type erased.
+///
+/// Everything stored under a function's id is created from that function's types,
+/// and the id of the type is kept to check it against the type it is used as.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct Erased(Rc<ErasedBox>);
+
+impl Erased {
+    pub(crate) fn new<C: ?Sized>(value: Box<C>) -> Self {
+        Self(Rc::new(ErasedBox::new(RefCell::new(value))))
+    }
+
+    /// The value as `C`, or `None` if it was created from another type.
+    ///
+    /// Safety: see `ErasedBox::get`.
+    pub(crate) unsafe fn get<C: ?Sized>(&self) -> Option<&RefCell<Box<C>>> {
+        unsafe { self.0.get() }
+    }
+
+    /// Name of the type this was created with.
+    pub(crate) fn type_name(&self) -> &'static str {
+        self.0.type_name
+    }
+}
+
+/// A value with its type erased.
+pub(crate) struct ErasedBox {
+    // `Box<T>`, as a raw pointer
+    ptr: NonNull<()>,
+    id: TypeId,
+    // for panic messages
+    pub(crate) type_name: &'static str,
+    drop: unsafe fn(NonNull<()>),
+}
+
+impl ErasedBox {
+    pub(crate) fn new<T>(value: T) -> Self {
+        unsafe fn drop_box<T>(ptr: NonNull<()>) {
+            // SAFETY: `ptr` was created from this type in `new`
+            drop(unsafe { Box::from_raw(ptr.cast::<T>().as_ptr()) });
+        }
+
+        Self {
+            ptr: NonNull::from(Box::leak(Box::new(value))).cast(),
+            id: erased_id::<T>(),
+            type_name: type_name::<T>(),
+            drop: drop_box::<T>,
+        }
+    }
+
+    /// The value as `T`, or `None` if it was created from another type.
+    ///
+    /// Safety: the ids of types don't include lifetimes, so `T` may only differ from the type this was created with by lifetimes,
+    /// and the value must not be used for longer than those lifetimes.
+    pub(crate) unsafe fn get<T>(&self) -> Option<&T> {
+        if self.id != erased_id::<T>() {
+            return None;
+        }
+
+        // SAFETY: the pointer was created from a `T` in `new`, as checked above
+        Some(unsafe { self.ptr.cast::<T>().as_ref() })
+    }
+}
+
+/// Id of `T` with its lifetimes erased, which `TypeId::of` can't give for types that aren't `'static`.
+///
+/// The closure is generic over `T` but captures nothing, so it is `'static` whatever the lifetimes of `T`.
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn erased_id<T: ?Sized>() -> TypeId {
+    (|| ()).type_id()
+}
+
+impl Drop for ErasedBox {
+    fn drop(&mut self) {
+        // SAFETY: `drop` was created with the type of `ptr`
+        unsafe { (self.drop)(self.ptr) }
+    }
+}
+
+/// A queued mock: the closure answering calls, and the predicate calls must match.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct MockReturn {
+    pub(crate) with: Erased,
+    pub(crate) when: Option<Erased>,
+    pub(crate) state: Rc<MockState>,
+}
 
 #[doc(hidden)]
 #[derive(Default)]
 pub struct MockStore {
     // (fn type_id) -> return_value
     mocks: RefCell<HashMap<TypeId, VecDeque<MockReturn>>>,
+    // (fn type_id) -> calls, oldest first
+    calls: RefCell<HashMap<TypeId, Vec<Call>>>,
+    // (fn type_id) -> expectations, in the order they were registered
+    expectations: RefCell<HashMap<TypeId, Vec<Rc<ExpectationState>>>>,
+    // whether real function bodies panic
+    strict: Cell<bool>,
+    // functions whose real bodies may run in strict mode
+    allowed: RefCell<HashSet<TypeId>>,
+    // functions mocked on the thread whose context this thread entered, where their mocks stay
+    mocked_elsewhere: RefCell<HashSet<TypeId>>,
+    // function whose next call runs its real body, set by `mock_wrap` to call the original
+    bypass: Cell<Option<TypeId>>,
+    // (fn type_id) -> number of calls running, mocked or not
+    depths: RefCell<HashMap<TypeId, usize>>,
+    // whether calls are only recorded for functions expected or spied on
+    unrecorded: Cell<bool>,
+    // functions that were mocked, expected or spied on; while unrecorded, calls of other functions skip the store
+    tracked: RefCell<HashSet<TypeId>>,
+    // functions that were expected or spied on, recorded even while unrecorded
+    recorded: RefCell<HashSet<TypeId>>,
+    // bumped whenever the functions that skip the store may change
+    generation: Cell<u64>,
+    // mocks selected for calls that haven't run them yet, innermost last
+    selected: RefCell<Vec<MockReturn>>,
+}
+
+// generations are unique across stores, since a thread's store can be swapped with a task's
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Whether a function was tracked at a generation of the store, cached where the function is called from.
+#[doc(hidden)]
+pub struct TrackedCache(Cell<Option<(u64, TypeId, bool)>>);
+
+impl TrackedCache {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self(Cell::new(None))
+    }
 }
 
 impl MockStore {
+    /// Queue a mock in its layer: overrides go to the front, fallbacks to the back,
+    /// and other mocks after the other mocks but before the fallbacks.
     pub(crate) fn add(&self, id: TypeId, value: MockReturn) {
-        {
-            if let Some(returns) = self.mocks.borrow_mut().get_mut(&id) {
-                returns.push_back(value);
-                return;
+        self.track(id);
+
+        let mut mocks = self.mocks.borrow_mut();
+        let returns = mocks.entry(id).or_default();
+
+        match value.state.layer {
+            Layer::Override => returns.push_front(value),
+            Layer::Queue => {
+                let index = returns
+                    .iter()
+                    .position(|r| r.state.layer == Layer::Fallback)
+                    .unwrap_or(returns.len());
+
+                returns.insert(index, value);
             }
+            Layer::Fallback => returns.push_back(value),
         }
-
-        self.mocks.borrow_mut().insert(id, vec![value].into());
     }
 
-    pub(crate) fn mock_exists(&self, id: TypeId) -> bool {
+    /// The queued mock of the function at `index`, with its predicate.
+    pub(crate) fn candidate(
+        &self,
+        id: TypeId,
+        index: usize,
+    ) -> Option<(Rc<MockState>, Option<Erased>)> {
         self.mocks
             .borrow()
-            .get(&id)
-            .map(|m| !m.is_empty())
-            .unwrap_or(false)
+            .get(&id)?
+            .get(index)
+            .map(|r| (r.state.clone(), r.when.clone()))
     }
 
-    pub(crate) fn get(&self, id: TypeId) -> Option<MockReturn> {
+    /// The mock with the given state, if it is still queued.
+    pub(crate) fn get(&self, id: TypeId, state: &Rc<MockState>) -> Option<MockReturn> {
         self.mocks
-            .borrow_mut()
-            .get_mut(&id)
-            .and_then(|returns| returns.pop_front())
+            .borrow()
+            .get(&id)?
+            .iter()
+            .find(|r| Rc::ptr_eq(&r.state, state))
+            .cloned()
+    }
+
+    /// Remove the mock with the given state, if it is still queued.
+    pub(crate) fn remove_mock(&self, id: TypeId, state: &Rc<MockState>) {
+        if let Some(returns) = self.mocks.borrow_mut().get_mut(&id) {
+            returns.retain(|r| !Rc::ptr_eq(&r.state, state));
+        }
+    }
+
+    pub(crate) fn push_selected(&self, mock: MockReturn) {
+        self.selected.borrow_mut().push(mock);
+    }
+
+    pub(crate) fn pop_selected(&self) -> Option<MockReturn> {
+        self.selected.borrow_mut().pop()
     }
 
     pub(crate) fn remove(&self, id: TypeId) {
         self.mocks.borrow_mut().remove(&id);
     }
 
+    /// Count the call for the function's expectations, and record it unless calls are unrecorded.
+    pub(crate) fn record(&self, id: TypeId, call: impl FnOnce() -> Call) {
+        if let Some(expectations) = self.expectations.borrow_mut().get_mut(&id) {
+            for expectation in expectations.iter().filter(|e| !e.answers) {
+                expectation.calls.set(expectation.calls.get() + 1);
+            }
+        }
+
+        if !self.unrecorded.get() || self.recorded.borrow().contains(&id) {
+            self.calls.borrow_mut().entry(id).or_default().push(call());
+        }
+    }
+
+    pub(crate) fn calls(&self, id: TypeId) -> Vec<Call> {
+        self.calls.borrow().get(&id).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn call_count(&self, id: TypeId) -> usize {
+        self.calls.borrow().get(&id).map(Vec::len).unwrap_or(0)
+    }
+
+    pub(crate) fn last_call(&self, id: TypeId) -> Option<Call> {
+        self.calls.borrow().get(&id).and_then(|c| c.last().cloned())
+    }
+
+    pub(crate) fn remove_calls(&self, id: TypeId) {
+        self.calls.borrow_mut().remove(&id);
+    }
+
+    pub(crate) fn expect(&self, id: TypeId, expectation: Rc<ExpectationState>) {
+        self.spy(id);
+
+        self.expectations
+            .borrow_mut()
+            .entry(id)
+            .or_default()
+            .push(expectation);
+    }
+
+    /// Remove a single expectation, returning whether it was still registered.
+    pub(crate) fn remove_expectation(
+        &self,
+        id: TypeId,
+        expectation: &Rc<ExpectationState>,
+    ) -> bool {
+        let mut expectations = self.expectations.borrow_mut();
+        let Some(registered) = expectations.get_mut(&id) else {
+            return false;
+        };
+        let len = registered.len();
+
+        registered.retain(|e| !Rc::ptr_eq(e, expectation));
+
+        registered.len() != len
+    }
+
+    /// Remove the expectations of the function, returning the failures.
+    pub(crate) fn verify(&self, id: TypeId) -> Vec<String> {
+        let expectations = self.expectations.borrow_mut().remove(&id);
+        let calls = self.calls(id);
+
+        expectations
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.check(&calls))
+            .collect()
+    }
+
+    /// Remove the expectations of all functions, returning the failures.
+    pub(crate) fn verify_all(&self) -> Vec<String> {
+        let ids = self
+            .expectations
+            .borrow()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+
+        ids.into_iter().flat_map(|id| self.verify(id)).collect()
+    }
+
+    /// Describe the mocks with calls left to answer, other than indefinite ones.
+    pub(crate) fn unused(&self) -> Vec<String> {
+        self.mocks
+            .borrow()
+            .values()
+            .flatten()
+            .filter(|r| r.state.expectation.is_none())
+            .filter_map(|r| {
+                let unused = r.state.unused()?;
+                let calls = if unused == 1 {
+                    "1 mocked call was".to_owned()
+                } else {
+                    format!("{unused} mocked calls were")
+                };
+
+                Some(format!("{calls} never made to `{}`", r.state.name))
+            })
+            .collect()
+    }
+
+    /// Set strict mode, returning whether it was set before.
+    pub(crate) fn set_strict(&self, strict: bool) -> bool {
+        if self.strict.get() != strict {
+            self.bump();
+        }
+
+        self.strict.replace(strict)
+    }
+
+    /// Set unrecorded calls, returning whether they were set before.
+    pub(crate) fn set_unrecorded(&self, unrecorded: bool) -> bool {
+        if self.unrecorded.get() != unrecorded {
+            self.bump();
+        }
+
+        self.unrecorded.replace(unrecorded)
+    }
+
+    /// Look for the mocks of the function from now on, even while calls are unrecorded,
+    /// returning whether it wasn't already.
+    pub(crate) fn track(&self, id: TypeId) -> bool {
+        let inserted = self.tracked.borrow_mut().insert(id);
+
+        if inserted {
+            self.bump();
+        }
+
+        inserted
+    }
+
+    /// Stop looking for the mocks of the function while calls are unrecorded, undoing `track`.
+    pub(crate) fn untrack(&self, id: TypeId) {
+        if self.tracked.borrow_mut().remove(&id) {
+            self.bump();
+        }
+    }
+
+    /// Record the calls of the function from now on, even while calls are unrecorded.
+    pub(crate) fn spy(&self, id: TypeId) {
+        self.track(id);
+        self.recorded.borrow_mut().insert(id);
+    }
+
+    /// Start a new generation, so call sites look up whether functions are tracked again.
+    fn bump(&self) {
+        self.generation
+            .set(NEXT_GENERATION.fetch_add(1, Ordering::Relaxed));
+    }
+
+    /// Exchange everything in this store with `other`, e.g. to hand a task's mocks to the thread polling it.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn swap(&self, other: &MockStore) {
+        self.mocks.swap(&other.mocks);
+        self.calls.swap(&other.calls);
+        self.expectations.swap(&other.expectations);
+        self.strict.swap(&other.strict);
+        self.allowed.swap(&other.allowed);
+        self.mocked_elsewhere.swap(&other.mocked_elsewhere);
+        self.bypass.swap(&other.bypass);
+        self.depths.swap(&other.depths);
+        self.unrecorded.swap(&other.unrecorded);
+        self.tracked.swap(&other.tracked);
+        self.recorded.swap(&other.recorded);
+        self.selected.swap(&other.selected);
+
+        self.bump();
+        other.bump();
+    }
+
+    /// Whether calls of the function must go through the store, using `cache` while the store's generation is the same.
+    pub(crate) fn is_tracked(&self, id: TypeId, cache: &TrackedCache) -> bool {
+        let generation = self.generation.get();
+
+        match cache.0.get() {
+            Some((cached, cached_id, tracked)) if cached == generation && cached_id == id => {
+                tracked
+            }
+            _ => {
+                let tracked = !self.unrecorded.get()
+                    || self.strict.get()
+                    || self.tracked.borrow().contains(&id);
+
+                cache.0.set(Some((generation, id, tracked)));
+                tracked
+            }
+        }
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict.get()
+    }
+
+    /// Let the real body of the function run in strict mode, returning whether it couldn't before.
+    pub(crate) fn allow_real(&self, id: TypeId) -> bool {
+        self.allowed.borrow_mut().insert(id)
+    }
+
+    pub(crate) fn disallow_real(&self, id: TypeId) {
+        self.allowed.borrow_mut().remove(&id);
+    }
+
+    /// Functions whose real bodies may run in strict mode.
+    pub(crate) fn allowed(&self) -> Vec<TypeId> {
+        self.allowed.borrow().iter().copied().collect()
+    }
+
+    /// Functions with mocks queued on this thread.
+    pub(crate) fn mocked(&self) -> Vec<TypeId> {
+        self.mocks
+            .borrow()
+            .iter()
+            .filter(|(_, mocks)| !mocks.is_empty())
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// Mark the function as mocked on the thread whose context this thread entered,
+    /// returning whether it wasn't already.
+    pub(crate) fn mock_elsewhere(&self, id: TypeId) -> bool {
+        self.mocked_elsewhere.borrow_mut().insert(id)
+    }
+
+    pub(crate) fn unmock_elsewhere(&self, id: TypeId) {
+        self.mocked_elsewhere.borrow_mut().remove(&id);
+    }
+
+    pub(crate) fn is_mocked_elsewhere(&self, id: TypeId) -> bool {
+        self.mocked_elsewhere.borrow().contains(&id)
+    }
+
+    /// Whether the real body of the function may run.
+    pub(crate) fn is_real_allowed(&self, id: TypeId) -> bool {
+        !self.strict.get() || self.allowed.borrow().contains(&id)
+    }
+
+    pub(crate) fn enter(&self, id: TypeId) {
+        *self.depths.borrow_mut().entry(id).or_default() += 1;
+    }
+
+    pub(crate) fn exit(&self, id: TypeId) {
+        if let Some(depth) = self.depths.borrow_mut().get_mut(&id) {
+            *depth = depth.saturating_sub(1);
+        }
+    }
+
+    /// Number of calls of the function running, including the current one.
+    pub(crate) fn depth(&self, id: TypeId) -> usize {
+        self.depths.borrow().get(&id).copied().unwrap_or(0)
+    }
+
+    /// Make the next call of the function skip its mocks and run its real body.
+    pub(crate) fn bypass(&self, id: TypeId) {
+        self.bypass.set(Some(id));
+    }
+
+    /// Stop bypassing mocks, if the call didn't already.
+    pub(crate) fn end_bypass(&self) {
+        self.bypass.set(None);
+    }
+
+    pub(crate) fn is_bypassed(&self, id: TypeId) -> bool {
+        self.bypass.get() == Some(id)
+    }
+
+    /// Whether the call is bypassing mocks, which ends with it.
+    pub(crate) fn take_bypass(&self, id: TypeId) -> bool {
+        let bypassed = self.is_bypassed(id);
+
+        if bypassed {
+            self.bypass.set(None);
+        }
+
+        bypassed
+    }
+
     pub(crate) fn clear(&self) {
-        self.mocks.borrow_mut().clear()
+        self.mocks.borrow_mut().clear();
+        self.calls.borrow_mut().clear();
+        self.expectations.borrow_mut().clear();
+        self.allowed.borrow_mut().clear();
+        self.mocked_elsewhere.borrow_mut().clear();
+        self.bypass.set(None);
+        self.tracked.borrow_mut().clear();
+        self.recorded.borrow_mut().clear();
+        self.bump();
     }
 }
@@ -0,0 +1,56 @@
+use std::thread;
+
+use crate::{
+    expect::assert_verified,
+    shared::{self, SharedScope},
+    MOCK_STORE,
+};
+
+/// Guard inserted by `#[mockem::test]`.
+///
+/// Clears the store when created, and checks it is left clean when dropped.
+/// Tests with shared mocks run in a shared scope; other tests don't see shared mocks.
+#[doc(hidden)]
+pub struct TestGuard {
+    // removes the shared mocks of the test when dropped
+    scope: Option<SharedScope>,
+}
+
+impl TestGuard {
+    pub fn new(strict: bool, shared: bool) -> Self {
+        crate::clear_mocks();
+        MOCK_STORE.with(|mock_store| mock_store.set_strict(strict));
+
+        Self {
+            scope: shared.then(shared::shared_scope),
+        }
+    }
+}
+
+impl Drop for TestGuard {
+    fn drop(&mut self) {
+        // the store is already gone if the thread is shutting down
+        let Ok(mut reports) = MOCK_STORE.try_with(|mock_store| {
+            let mut reports = mock_store.unused();
+            reports.extend(mock_store.verify_all());
+
+            mock_store.clear();
+            mock_store.set_strict(false);
+            reports
+        }) else {
+            return;
+        };
+        // `Send` mocks are reported like the thread's own
+        reports.extend(shared::carried_unused());
+        shared::clear_carried();
+
+        if let Some(scope) = &self.scope {
+            reports.extend(scope.unused());
+        }
+
+        // the test already failed
+        if !thread::panicking() {
+            assert_verified(reports);
+        }
+    }
+}
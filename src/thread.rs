@@ -0,0 +1,35 @@
+//! Threads that carry the mock context of the thread starting them.
+//!
+//! See `mockem::context` for what the context carries.
+
+use std::thread::{self, JoinHandle};
+
+use crate::context;
+
+/// Spawn a thread, as with `std::thread::spawn`, in the mock context of the current thread.
+///
+/// Functions mocked with `MockCall` on the current thread panic when the new thread calls them,
+/// unless they also have a shared or `Send` mock.
+///
+/// ```rust
+/// # #[mockem::mock]
+/// # fn fetch() -> u8 { 0 }
+/// use mockem::MockShared;
+///
+/// let _scope = mockem::shared_scope();
+/// fetch.mock_shared(None, || 1);
+///
+/// assert_eq!(mockem::thread::spawn(fetch).join().unwrap(), 1);
+/// ```
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let context = context::capture();
+
+    thread::spawn(move || {
+        let _context = context.enter();
+        f()
+    })
+}
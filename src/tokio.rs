@@ -0,0 +1,158 @@
+//! Shared scopes for tasks, which follow them across the worker threads of a multi-thread runtime.
+//!
+//! Available with the `tokio` feature.
+//!
+//! On a multi-thread runtime, a task can resume on another worker thread after an `.await`,
+//! where the mocks its thread queued with `MockCall` don't apply.
+//! In a task scope, mocks queued with `MockCall` follow the task instead, since the scope keeps them between polls.
+//! Mocks queued with `MockShared::mock_shared` there answer the task wherever it runs,
+//! as well as the tasks and blocking closures started with `spawn` and `spawn_blocking` from here.
+//! These carry the mock context of the task or thread starting them, see `mockem::context`:
+//! functions mocked there with `MockCall` panic when they call them without a shared or `Send` mock.
+//! Tasks started with `tokio::spawn` get no context, so they only see the mocks of the worker thread they run on.
+//!
+//! Like `SharedScope`s, task scopes don't see each other's mocks, so tests using them run in parallel.
+//! With the feature, `#[mockem::test(shared)]` runs async tests in a task scope.
+//!
+//! ```rust
+//! # #[mockem::mock]
+//! # async fn fetch() -> u8 { 0 }
+//! use mockem::MockShared;
+//!
+//! #[mockem::test(shared)]
+//! #[tokio::test(flavor = "multi_thread")]
+//! async fn test_fn() {
+//!     fetch.mock_shared(None, || 1);
+//!
+//!     tokio::task::yield_now().await;
+//!     assert_eq!(fetch().await, 1);
+//!
+//!     let child = mockem::tokio::spawn(async { fetch().await });
+//!     assert_eq!(child.await.unwrap(), 1);
+//! }
+//! ```
+
+use std::{
+    future::{self, Future},
+    pin::pin,
+    sync::Arc,
+};
+
+use ::tokio::task::{self, JoinHandle};
+
+use crate::{
+    context,
+    shared::{self, Scope},
+    store::MockStore,
+    MOCK_STORE,
+};
+
+::tokio::task_local! {
+    static SCOPE: Arc<Scope>;
+}
+
+/// Scope of the current task, if it runs in one.
+pub(crate) fn task_scope() -> Option<Arc<Scope>> {
+    SCOPE.try_with(Arc::clone).ok()
+}
+
+/// Run `future` in a new task scope, whose shared mocks are removed when it completes or is dropped.
+///
+/// The mocks the task queues with `MockCall`, and its recorded calls and expectations, are kept in the scope
+/// and handed to the thread polling the task, so they follow it too.
+pub async fn scope<F: Future>(future: F) -> F::Output {
+    // closes the scope, even if the future is dropped before completing
+    struct ClosingScope(Arc<Scope>);
+
+    impl Drop for ClosingScope {
+        fn drop(&mut self) {
+            shared::close_scope(&self.0);
+        }
+    }
+
+    let scope = ClosingScope(shared::open_scope());
+
+    in_scope(scope.0.clone(), MockStore::default(), future)
+        .await
+        .0
+}
+
+/// Run `future` in `scope` with `store` as the task's mock store, returning the store once it completes.
+pub(crate) fn in_scope<F: Future>(
+    scope: Arc<Scope>,
+    store: MockStore,
+    future: F,
+) -> impl Future<Output = (F::Output, MockStore)> {
+    // puts the task's store in the thread's place while the task is polled, and back after
+    struct Polling<'a>(&'a MockStore);
+
+    impl<'a> Polling<'a> {
+        fn new(store: &'a MockStore) -> Self {
+            MOCK_STORE.with(|mock_store| mock_store.swap(store));
+            Self(store)
+        }
+    }
+
+    impl Drop for Polling<'_> {
+        fn drop(&mut self) {
+            let _ = MOCK_STORE.try_with(|mock_store| mock_store.swap(self.0));
+        }
+    }
+
+    // wrapped outside the future, so the future is `Send` whenever `future` is
+    let mut store = TaskStore(store);
+
+    async move {
+        let mut future = pin!(SCOPE.scope(scope, future));
+
+        let task_store = &mut store;
+        let output = future::poll_fn(move |cx| {
+            let _polling = Polling::new(&task_store.0);
+            future.as_mut().poll(cx)
+        })
+        .await;
+
+        (output, store.0)
+    }
+}
+
+/// Mock store of a task scope, holding the task's `MockCall` mocks while it isn't polled.
+struct TaskStore(MockStore);
+
+// SAFETY: the store is only used by the thread polling the task, while it does,
+// and holds what the task queued, which only the task uses
+unsafe impl Send for TaskStore {}
+
+/// Spawn a task, as with `tokio::spawn`, in the mock context of the current task or thread.
+///
+/// The task enters the context whenever it is polled, on whichever worker thread.
+/// See `mockem::context` for what the context carries.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let context = context::capture();
+    let mut future = Box::pin(future);
+
+    task::spawn(future::poll_fn(move |cx| {
+        let _context = context.enter();
+        future.as_mut().poll(cx)
+    }))
+}
+
+/// Run a blocking closure, as with `tokio::task::spawn_blocking`, in the mock context of the current task or thread.
+///
+/// See `mockem::context` for what the context carries.
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let context = context::capture();
+
+    task::spawn_blocking(move || {
+        let _context = context.enter();
+        f()
+    })
+}
@@ -0,0 +1,108 @@
+use std::future::Future;
+
+use mockem::{mock, MockAsync, MockCall};
+
+struct Client;
+
+trait Load {
+    async fn load(&self, id: u32) -> String;
+
+    fn load_send(&self, id: u32) -> impl Future<Output = String> + Send;
+}
+
+#[mock]
+impl Load for Client {
+    async fn load(&self, id: u32) -> String {
+        id.to_string()
+    }
+
+    fn load_send(&self, id: u32) -> impl Future<Output = String> + Send {
+        async move { format!("sent {id}") }
+    }
+}
+
+#[mock]
+trait Store {
+    async fn store(&self, name: &str) -> bool {
+        !name.is_empty()
+    }
+
+    fn store_send(&self, name: &str) -> impl Future<Output = bool> + Send {
+        let long = name.len() > 1;
+        async move { long }
+    }
+}
+
+impl Store for Client {}
+
+trait Fetch {
+    fn fetch(&self, id: u32) -> impl Future<Output = u32> + Send;
+}
+
+// implemented with `async fn` despite the `Send` bound
+#[mock]
+impl Fetch for Client {
+    async fn fetch(&self, id: u32) -> u32 {
+        id
+    }
+}
+
+#[mock]
+fn exists(name: &str) -> impl Future<Output = bool> {
+    let found = !name.is_empty();
+    async move { found }
+}
+
+struct Counter(u8);
+
+#[mock]
+impl Counter {
+    fn get(&self) -> impl Future<Output = u8> {
+        let count = self.0;
+        async move { count }
+    }
+}
+
+fn assert_send<F: Future + Send>(future: F) -> F {
+    future
+}
+
+#[tokio::test]
+async fn test_async_fn_in_trait() {
+    <Client as Load>::load.mock_once(|_, id| format!("mocked {id}"));
+    <Client as Store>::store.mock_once(|_, _| false);
+
+    assert_eq!(Client.load(1).await, "mocked 1");
+    assert_eq!(Client.load(1).await, "1");
+    assert!(!Client.store("a").await);
+    assert!(Client.store("a").await);
+}
+
+#[tokio::test]
+async fn test_impl_future_in_trait() {
+    <Client as Load>::load_send.mock_once_async(|_, id| async move { format!("mocked {id}") });
+    <Client as Store>::store_send.mock_once(|_, _| true);
+
+    // the futures stay `Send`, mocked or not
+    assert_eq!(assert_send(Client.load_send(1)).await, "mocked 1");
+    assert_eq!(assert_send(Client.load_send(1)).await, "sent 1");
+    assert!(assert_send(Client.store_send("a")).await);
+    assert!(!assert_send(Client.store_send("a")).await);
+
+    let spawned = tokio::spawn(async { Client.load_send(2).await });
+    assert_eq!(spawned.await.unwrap(), "sent 2");
+}
+
+#[tokio::test]
+async fn test_borrowed_args() {
+    exists.mock_once(|_| true);
+    Counter::get.mock_once_async(|_| async { 5 });
+    <Client as Fetch>::fetch.mock_once(|_, _| 2);
+
+    assert!(exists("").await);
+    assert!(!exists("").await);
+    assert_eq!(Counter(1).get().await, 5);
+    assert_eq!(Counter(1).get().await, 1);
+    assert_eq!(assert_send(Client.fetch(1)).await, 2);
+    assert_eq!(assert_send(Client.fetch(1)).await, 1);
+}
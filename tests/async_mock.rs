@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mockem::{mock, MockAsync, MockCall};
+use tokio::sync::oneshot;
+
+#[mock]
+async fn fetch(id: u32) -> u32 {
+    id
+}
+
+#[mock]
+async fn fetch_twice(id: u32) -> u32 {
+    fetch(id).await + fetch(id).await
+}
+
+struct Client;
+
+#[async_trait]
+trait Load {
+    async fn load(&self, id: u32) -> String;
+}
+
+#[mock]
+#[async_trait]
+impl Load for Client {
+    async fn load(&self, id: u32) -> String {
+        id.to_string()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_async_mock() {
+    fetch.mock_once(|_| 10);
+    fetch_twice.mock_once_async(|id| async move {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // other mocks still answer
+        fetch(id).await + 1
+    });
+
+    assert_eq!(fetch_twice(1).await, 11);
+
+    <Client as Load>::load.mock_once_async(|_, id| async move {
+        tokio::task::yield_now().await;
+        format!("mocked {id}")
+    });
+
+    assert_eq!(Client.load(1).await, "mocked 1");
+}
+
+#[tokio::test]
+async fn test_interleaved() {
+    let (tx1, rx1) = oneshot::channel();
+    let (tx2, rx2) = oneshot::channel();
+    let mut receivers = vec![rx2, rx1];
+
+    let handle = fetch.mock_repeat_async(Some(2), move |_| {
+        let rx = receivers.pop().unwrap();
+        async move { rx.await.unwrap() }
+    });
+
+    let both = async { tokio::join!(fetch(1), fetch(2)) };
+    let answer = async {
+        // answered in the reverse order of the calls
+        tx2.send(20).unwrap();
+        tokio::task::yield_now().await;
+        tx1.send(10).unwrap();
+    };
+    let ((first, second), ()) = tokio::join!(both, answer);
+
+    assert_eq!((first, second), (10, 20));
+    assert!(handle.is_consumed());
+}
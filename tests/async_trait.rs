@@ -12,7 +12,45 @@ trait Bar {
 #[async_trait]
 impl Bar for Foo {
     async fn bar(&self) -> String {
-        format!("bar")
+        "bar".to_owned()
+    }
+}
+
+#[async_trait]
+trait Qux {
+    async fn qux(&self, n: u32) -> u32;
+}
+
+// expanded by `#[async_trait]` before `#[mock]`
+#[async_trait]
+#[mock]
+trait Corge {
+    async fn corge(&self, (a, b): (u32, u32)) -> u32 {
+        a + b
+    }
+}
+
+#[async_trait]
+#[mock]
+impl Qux for Foo {
+    #[tracing::instrument(skip(self))]
+    async fn qux(&self, n: u32) -> u32 {
+        n
+    }
+}
+
+impl Corge for Foo {}
+
+#[async_trait]
+trait Grault {
+    async fn grault(&self, n: u32) -> u32;
+}
+
+#[async_trait]
+#[mock(eager)]
+impl Grault for Foo {
+    async fn grault(&self, n: u32) -> u32 {
+        n
     }
 }
 
@@ -26,3 +64,25 @@ async fn test_async_trait() {
 
     assert_eq!(&baz().await, "Hello, mockem!");
 }
+
+#[tokio::test]
+async fn test_async_trait_below() {
+    <Foo as Qux>::qux.mock_once(|_, n: u32| n + 1);
+    <Foo as Corge>::corge.mock_once(|_, (a, b): (u32, u32)| a * b);
+
+    assert_eq!(Foo.qux(1).await, 2);
+    assert_eq!(Foo.qux(1).await, 1);
+    assert_eq!(Foo.corge((2, 3)).await, 6);
+    assert_eq!(Foo.corge((2, 3)).await, 5);
+}
+
+#[tokio::test]
+async fn test_async_trait_eager() {
+    <Foo as Grault>::grault.mock_once(|_, _| 10);
+
+    // both calls are made before either future is polled
+    let first = Foo.grault(1);
+    let second = Foo.grault(2);
+
+    assert_eq!(tokio::join!(first, second), (10, 2));
+}
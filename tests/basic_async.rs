@@ -2,7 +2,7 @@ use mockem::{mock, MockCall};
 
 #[mock]
 async fn foo() -> String {
-    format!("foo")
+    "foo".to_owned()
 }
 
 struct Foo;
@@ -2,7 +2,7 @@ use mockem::{mock, ClearMocks, MockCall};
 
 #[mock]
 fn foo() -> String {
-    format!("foo")
+    "foo".to_owned()
 }
 
 struct Foo;
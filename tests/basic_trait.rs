@@ -9,7 +9,7 @@ trait Bar {
 #[mock]
 impl Bar for Foo {
     fn bar(&self) -> String {
-        format!("bar")
+        "bar".to_owned()
     }
 }
 
@@ -0,0 +1,118 @@
+use std::thread;
+
+use mockem::{mock, AllowReal, MockCall, MockSend, MockShared};
+
+#[mock]
+fn fetch(key: &str) -> String {
+    format!("real {key}")
+}
+
+#[mock]
+fn now() -> u64 {
+    0
+}
+
+#[mockem::test(shared)]
+fn test_context() {
+    fetch.mock_shared(None, |key| format!("shared {key}"));
+
+    let context = mockem::context::capture();
+    let worker = thread::spawn(move || {
+        let _context = context.enter();
+        fetch("a")
+    });
+
+    assert_eq!(worker.join().unwrap(), "shared a");
+    assert_eq!(
+        mockem::thread::spawn(|| fetch("b")).join().unwrap(),
+        "shared b"
+    );
+}
+
+#[mockem::test(strict)]
+fn test_spawn_strict() {
+    now.allow_real();
+
+    assert_eq!(mockem::thread::spawn(now).join().unwrap(), 0);
+    assert!(mockem::thread::spawn(|| fetch("a")).join().is_err());
+
+    // the context is left with the guard
+    let context = mockem::context::capture();
+    thread::spawn(move || {
+        drop(context.enter());
+        assert_eq!(fetch("a"), "real a");
+    })
+    .join()
+    .unwrap();
+}
+
+#[mockem::test(shared)]
+fn test_local_mocks_stay() {
+    fetch.mock_repeat(None, |key| format!("local {key}"));
+
+    // the closure isn't `Send`, so it can't answer other threads
+    let panic = mockem::thread::spawn(|| fetch("a")).join().unwrap_err();
+    assert!(panic
+        .downcast_ref::<String>()
+        .unwrap()
+        .contains("stay on their thread"));
+
+    fetch.mock_shared(None, |key| format!("shared {key}"));
+    assert_eq!(
+        mockem::thread::spawn(|| fetch("b")).join().unwrap(),
+        "shared b"
+    );
+    assert_eq!(fetch("c"), "local c");
+}
+
+#[mockem::test]
+fn test_send_mocks() {
+    fetch.mock_once_send(|key| format!("send {key}"));
+    now.mock_repeat_send(Some(2), || 1);
+
+    // carried into the worker without a shared scope, and used up there
+    let context = mockem::context::capture();
+    let worker = thread::spawn(move || {
+        let _context = context.enter();
+        (fetch("a"), now())
+    });
+
+    assert_eq!(worker.join().unwrap(), ("send a".to_owned(), 1));
+    assert_eq!(fetch("b"), "real b");
+    assert_eq!(now(), 1);
+    assert_eq!(mockem::thread::spawn(now).join().unwrap(), 0);
+}
+
+#[mockem::test]
+fn test_send_mocks_queued_after_capture() {
+    let context = mockem::context::capture();
+    now.mock_repeat_send(None, || 1);
+
+    // local mocks answer first
+    now.mock_once(|| 2);
+    assert_eq!(now(), 2);
+
+    let worker = thread::spawn(move || {
+        let _context = context.enter();
+        let answer = now();
+
+        // removed on every thread
+        mockem::clear_mocks();
+        answer
+    });
+
+    assert_eq!(worker.join().unwrap(), 1);
+    assert_eq!(now(), 0);
+
+    // the thread gets a new carried scope
+    now.mock_once_send(|| 3);
+    assert_eq!(now(), 3);
+}
+
+#[mockem::test]
+#[should_panic(expected = "1 mocked call was never made to `context::now`")]
+fn test_unused_send_mocks() {
+    now.mock_once_send(|| 1);
+    // indefinite mocks are never reported
+    fetch.mock_repeat_send(None, |key| format!("send {key}"));
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use mockem::{mock, DeferredStatus, MockDeferred};
+
+#[mock]
+async fn fetch(id: u32) -> Result<u32, String> {
+    Ok(id)
+}
+
+#[tokio::test]
+async fn test_deferred() {
+    let first = fetch.mock_deferred();
+    let second = fetch.mock_deferred();
+
+    assert_eq!(first.status(), DeferredStatus::NotCalled);
+
+    let calls = tokio::spawn(async { tokio::join!(fetch(1), fetch(2)) });
+    tokio::task::yield_now().await;
+
+    assert_eq!(first.status(), DeferredStatus::Waiting);
+    assert!(first.was_polled());
+
+    // completed in the reverse order of the calls
+    second.complete(Ok(20));
+    first.fail("unavailable".to_owned());
+
+    assert_eq!(
+        calls.await.unwrap(),
+        (Err("unavailable".to_owned()), Ok(20))
+    );
+    assert_eq!(first.status(), DeferredStatus::Completed);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_cancelled() {
+    let deferred = fetch.mock_deferred();
+
+    let fetched = tokio::time::timeout(Duration::from_secs(1), fetch(1)).await;
+
+    assert!(fetched.is_err());
+    assert_eq!(deferred.status(), DeferredStatus::Cancelled);
+}
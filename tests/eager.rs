@@ -0,0 +1,68 @@
+use mockem::{mock, MockCall};
+
+#[mock(eager)]
+async fn fetch(id: u32) -> u32 {
+    id
+}
+
+struct Client {
+    prefix: String,
+}
+
+#[mock(eager)]
+impl Client {
+    async fn load(&self, name: &str, suffix: &str) -> String {
+        format!("{}{name}{suffix}", self.prefix)
+    }
+}
+
+#[mock]
+trait Load {
+    async fn count(&self) -> u32 {
+        1
+    }
+}
+
+impl Load for Client {}
+
+#[tokio::test]
+async fn test_eager() {
+    fetch.mock_once(|_| 10);
+
+    // both calls are made before either future is polled
+    let first = fetch(1);
+    let second = fetch(2);
+    fetch.mock_once(|_| 30);
+
+    assert_eq!(tokio::join!(first, second), (10, 2));
+    assert_eq!(fetch(3).await, 30);
+}
+
+#[tokio::test]
+async fn test_eager_borrowed() {
+    let client = Client {
+        prefix: "real ".to_owned(),
+    };
+
+    Client::load.mock_once(|_, name, suffix| format!("mocked {name}{suffix}"));
+
+    let mocked = client.load("a", "!");
+    let real = client.load("b", "?");
+    mockem::clear_mocks();
+
+    assert_eq!(mocked.await, "mocked a!");
+    assert_eq!(real.await, "real b?");
+}
+
+#[tokio::test]
+async fn test_lazy_default() {
+    let client = Client {
+        prefix: String::new(),
+    };
+
+    // picked when first polled, as without `eager`
+    let future = client.count();
+    <Client as Load>::count.mock_once(|_| 2);
+
+    assert_eq!(future.await, 2);
+}
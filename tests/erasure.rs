@@ -0,0 +1,117 @@
+// Exercises the type-erased mock storage with references, trait methods and async functions;
+// run under Miri with `cargo +nightly miri test --test erasure`, as CI does for the whole suite.
+
+use std::{
+    future::Future,
+    panic,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use mockem::{matchers::*, mock, MockCall, MockShared, MockWhen};
+
+#[mock]
+fn longest<'a>(a: &'a str, b: &'a str) -> &'a str {
+    if a.len() >= b.len() {
+        a
+    } else {
+        b
+    }
+}
+
+#[mock]
+fn push(buf: &mut Vec<u8>, byte: u8) -> usize {
+    buf.push(byte);
+    buf.len()
+}
+
+trait Store {
+    fn get(&self, key: &str) -> Option<String>;
+}
+
+struct Memory(Vec<(String, String)>);
+
+#[mock]
+impl Store for Memory {
+    fn get(&self, key: &str) -> Option<String> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+}
+
+#[mock]
+async fn load(key: String) -> String {
+    format!("loaded {key}")
+}
+
+#[test]
+fn test_references() {
+    longest.mock_when(|a, _| a.is_empty(), |_, b| b);
+
+    let a = String::new();
+    let b = String::from("b");
+    assert_eq!(longest(&a, &b), "b");
+    assert_eq!(longest("aa", "b"), "aa");
+
+    push.mock_matching((any(), eq(0)), |buf, _| {
+        buf.clear();
+        0
+    });
+
+    let mut buf = vec![1, 2];
+    assert_eq!(push(&mut buf, 3), 3);
+    assert_eq!(push(&mut buf, 0), 0);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_trait_method() {
+    let memory = Memory(vec![("a".to_owned(), "1".to_owned())]);
+    <Memory as Store>::get.mock_once(|_, key| Some(format!("mocked {key}")));
+
+    assert_eq!(memory.get("a"), Some("mocked a".to_owned()));
+    assert_eq!(memory.get("a"), Some("1".to_owned()));
+}
+
+#[tokio::test]
+async fn test_async() {
+    load.mock_once(|key| format!("mocked {key}"));
+
+    assert_eq!(load("a".to_owned()).await, "mocked a");
+    assert_eq!(load("b".to_owned()).await, "loaded b");
+}
+
+#[tokio::test]
+#[should_panic(expected = "mock of `erasure::load` has the wrong type")]
+async fn test_wrong_type() {
+    // `load` itself returns a future, so this mocks `load` as a function returning a future,
+    // which is not how `load` looks for its mocks
+    load.mock_once(load);
+
+    load("a".to_owned()).await;
+}
+
+#[mockem::test(shared)]
+#[tokio::test]
+#[should_panic(expected = "shared mock of `erasure::load` has the wrong type")]
+async fn test_wrong_shared_type() {
+    load.mock_shared(Some(1), load);
+
+    // the mock isn't used up by the calls it can't answer
+    assert!(panic::catch_unwind(|| poll_once(load("a".to_owned()))).is_err());
+    let _ = poll_once(load("b".to_owned()));
+}
+
+fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(NoopWaker));
+    pin!(future).poll(&mut Context::from_waker(&waker))
+}
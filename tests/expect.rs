@@ -0,0 +1,49 @@
+use mockem::{mock, ExpectCalls, MockCall, Times};
+
+#[mock]
+fn save(key: &str) -> bool {
+    !key.is_empty()
+}
+
+#[mock]
+fn load(key: &str) -> Option<String> {
+    Some(key.to_owned())
+}
+
+#[test]
+fn test_expect_calls() {
+    save.expect_calls(Times::exactly(2));
+    load.expect_calls(Times::never());
+    save.mock_once(|_| false);
+
+    assert!(!save("a"));
+    assert!(save("b"));
+
+    save.verify();
+    mockem::verify();
+}
+
+#[test]
+#[should_panic(
+    expected = "was expected to be called at least 2 times, but was called 1 time\n    expect::save(\"a\")"
+)]
+fn test_expect_calls_unmet() {
+    save.expect_calls(Times::at_least(2));
+
+    save("a");
+
+    mockem::verify();
+}
+
+#[test]
+#[should_panic(
+    expected = "`expect::load` was expected to be called exactly 0 times, but was called 1 time"
+)]
+fn test_expect_never() {
+    load.expect_calls(Times::never());
+    save.expect_calls(Times::at_most(1));
+
+    load("a");
+
+    mockem::verify();
+}
@@ -0,0 +1,55 @@
+use mockem::{matchers::*, mock, ExpectCalls, Times};
+
+#[mock]
+fn connect(addr: &str) -> bool {
+    !addr.is_empty()
+}
+
+#[mock]
+fn send(msg: &str, retries: u32) -> usize {
+    msg.len()
+}
+
+#[test]
+fn test_expectation_builder() {
+    let first = send.expect().times(Times::exactly(2)).returning(|_, _| 1);
+    let _second = send.expect().with((eq("ping"), any())).returning(|_, _| 2);
+
+    assert_eq!(send("hello", 0), 1);
+    assert_eq!(send("hello", 0), 1);
+    assert_eq!(first.call_count(), 2);
+
+    // the first expectation is saturated, so the next matching one answers
+    assert_eq!(send("ping", 0), 2);
+    // unless there is none
+    assert_eq!(send("hello", 0), 1);
+    assert_eq!(first.call_count(), 3);
+
+    // the over-call would fail `first` on drop
+    mockem::clear_mocks();
+}
+
+#[test]
+#[should_panic(
+    expected = "`expectation::send` was expected to be called exactly 1 time with (to equal \"ping\", to be greater than 0), but was called 0 times\n    expectation::send(\"pong\", 1): expected arg #1 to equal \"ping\" but got \"pong\""
+)]
+fn test_expectation_unmet_on_drop() {
+    let _expectation = send
+        .expect()
+        .with((eq("ping"), gt(0)))
+        .times(1)
+        .returning(|_, _| 0);
+
+    assert_eq!(send("pong", 1), 4);
+}
+
+#[test]
+#[should_panic(
+    expected = "`expectation::send` was called before `expectation::connect` was called at least 1 time"
+)]
+fn test_expectation_after() {
+    let connected = connect.expect().returning(|_| true);
+    let _sent = send.expect().after(&connected).returning(|_, _| 0);
+
+    send("hello", 0);
+}
@@ -0,0 +1,43 @@
+use mockem::{mock, MockCall, Spy};
+
+#[mock]
+fn square(n: u64) -> u64 {
+    n * n
+}
+
+#[test]
+fn test_untracked_calls_skip_the_store() {
+    // never mocked, so not recorded
+    let sum = mockem::unrecorded(|| (0..1000).map(square).sum::<u64>());
+    assert_eq!(sum, 332_833_500);
+    assert_eq!(square.call_count(), 0);
+
+    mockem::unrecorded(|| {
+        square.spy();
+        assert_eq!(square(3), 9);
+        assert_eq!(square.call_count(), 1);
+
+        // mocked, but neither spied on nor expected anymore
+        mockem::clear_mocks();
+        square.mock_once(|_| 0);
+        assert_eq!(square(3), 0);
+        assert_eq!(square.call_count(), 0);
+    });
+
+    assert_eq!(square(3), 9);
+    assert_eq!(square.call_count(), 1);
+}
+
+#[test]
+fn test_mocks_added_between_calls() {
+    mockem::unrecorded(|| {
+        for n in 0..3 {
+            if n == 1 {
+                // call sites that saw `square` untracked notice it is mocked now
+                square.mock_once(|_| 0);
+            }
+
+            assert_eq!(square(2), if n == 1 { 0 } else { 4 });
+        }
+    });
+}
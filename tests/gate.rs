@@ -0,0 +1,83 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use mockem::{mock, MockGated};
+
+#[mock]
+fn reserve(_id: u32) -> bool {
+    true
+}
+
+/// Submits an order once, unless the reservation fails.
+fn submit(id: u32, submitted: &Mutex<Vec<u32>>) {
+    if reserve(id) {
+        submitted.lock().unwrap().push(id);
+    }
+}
+
+#[mockem::test(shared)]
+fn test_double_submit() {
+    let gate = reserve.mock_gated();
+    let submitted = Arc::new(Mutex::new(Vec::new()));
+
+    let threads = (0..2)
+        .map(|_| {
+            let submitted = submitted.clone();
+            mockem::thread::spawn(move || submit(1, &submitted))
+        })
+        .collect::<Vec<_>>();
+
+    // both submissions are past the check before either reserves
+    gate.wait_parked(2);
+    assert_eq!(gate.parked(), 2);
+
+    gate.release(true);
+    gate.release(true);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // the race: the order was submitted twice
+    assert_eq!(*submitted.lock().unwrap(), [1, 1]);
+    assert_eq!(gate.parked(), 0);
+}
+
+#[mockem::test(shared)]
+fn test_released_early() {
+    let gate = reserve.mock_gated();
+
+    gate.release(false);
+
+    assert!(!mockem::thread::spawn(|| reserve(1)).join().unwrap());
+}
+
+#[mockem::test(shared)]
+fn test_entered_threads() {
+    let gate = reserve.mock_gated();
+    let submitted = Arc::new(Mutex::new(Vec::new()));
+
+    // threads started otherwise park too once they enter the context
+    let threads = (0..2)
+        .map(|_| {
+            let submitted = submitted.clone();
+            let context = mockem::context::capture();
+            thread::spawn(move || {
+                let _context = context.enter();
+                submit(1, &submitted)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    gate.wait_parked(2);
+    gate.release(true);
+    gate.release(false);
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(*submitted.lock().unwrap(), [1]);
+}
@@ -0,0 +1,58 @@
+use mockem::{mock, MockCall, MockGuard};
+
+#[mock]
+fn fetch(id: u32) -> String {
+    format!("real {id}")
+}
+
+#[test]
+fn test_handle() {
+    let first = fetch.mock_repeat(Some(2), |id| format!("first {id}"));
+    let second = fetch.mock_once(|id| format!("second {id}"));
+
+    assert_eq!(first.remaining(), Some(2));
+    assert_eq!(fetch(1), "first 1");
+    assert_eq!(first.remaining(), Some(1));
+    assert!(!first.is_consumed());
+
+    // only cancels `first`; `second` stays queued
+    first.cancel();
+    assert!(first.is_consumed());
+    assert_eq!(first.remaining(), Some(0));
+
+    assert_eq!(fetch(2), "second 2");
+    assert!(second.is_consumed());
+    assert_eq!(fetch(3), "real 3");
+
+    let indefinite = fetch.mock_repeat(None, |_| "mocked".to_owned());
+    assert_eq!(indefinite.remaining(), None);
+}
+
+#[test]
+fn test_repeat_zero() {
+    let none = fetch.mock_repeat(Some(0), |_| "never".to_owned());
+
+    assert!(none.is_consumed());
+    assert_eq!(fetch(1), "real 1");
+}
+
+fn mock_fetch() -> MockGuard {
+    fetch
+        .mock_repeat(None, |id| format!("guarded {id}"))
+        .guard()
+}
+
+#[test]
+fn test_guard() {
+    {
+        let guard = mock_fetch();
+        fetch.mock_once(|id| format!("other {id}"));
+
+        assert_eq!(fetch(1), "guarded 1");
+        assert_eq!(guard.remaining(), None);
+    }
+
+    // the guarded mock is gone; the other mock is still queued
+    assert_eq!(fetch(2), "other 2");
+    assert_eq!(fetch(3), "real 3");
+}
@@ -0,0 +1,62 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use mockem::{mock, MockCall, MockPending};
+
+#[mock]
+async fn fetch(id: u32) -> u32 {
+    id
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[test]
+fn test_yield_times() {
+    fetch.mock_once(|_| 10).yield_times(3);
+
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    let mut fetched = pin!(fetch(1));
+
+    for _ in 0..3 {
+        assert!(fetched.as_mut().poll(&mut cx).is_pending());
+    }
+    assert_eq!(fetched.as_mut().poll(&mut cx), Poll::Ready(10));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_pending() {
+    let handle = fetch.mock_pending();
+
+    let fetched = tokio::time::timeout(Duration::from_secs(5), fetch(1)).await;
+
+    assert!(fetched.is_err());
+    assert!(handle.is_consumed());
+    assert_eq!(fetch(1).await, 1);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn test_with_delay() {
+    use tokio::time::{timeout, Instant};
+
+    fetch
+        .mock_repeat(Some(2), |_| 10)
+        .with_delay(Duration::from_secs(2));
+
+    // times out, but the call was answered
+    assert!(timeout(Duration::from_secs(1), fetch(1)).await.is_err());
+
+    let start = Instant::now();
+    assert_eq!(fetch(1).await, 10);
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+}
@@ -0,0 +1,48 @@
+use mockem::{mock, MockCall};
+
+#[mock]
+fn fetch(id: u32) -> String {
+    format!("real {id}")
+}
+
+#[test]
+fn test_queue_order() {
+    fetch.mock_repeat(Some(2), |_| "a".to_owned());
+    fetch.mock_once(|_| "b".to_owned());
+
+    // an entry stays at the front until it is used up
+    assert_eq!(fetch(1), "a");
+    assert_eq!(fetch(1), "a");
+    assert_eq!(fetch(1), "b");
+    assert_eq!(fetch(1), "real 1");
+}
+
+#[test]
+fn test_indefinite_hides_later_mocks() {
+    fetch.mock_repeat(None, |_| "indefinite".to_owned());
+    fetch.mock_once(|_| "once".to_owned());
+
+    // indefinite mocks no longer take turns with the mocks queued after them
+    assert_eq!(fetch(1), "indefinite");
+    assert_eq!(fetch(1), "indefinite");
+}
+
+#[test]
+fn test_override_and_fallback() {
+    fetch.mock_fallback(|id| format!("default {id}"));
+    fetch.mock_repeat(None, |_| "indefinite".to_owned());
+    fetch.mock_override(Some(1), |_| "override".to_owned());
+
+    assert_eq!(fetch(1), "override");
+    assert_eq!(fetch(1), "indefinite");
+
+    mockem::clear_mocks();
+
+    fetch.mock_fallback(|id| format!("default {id}"));
+    // queued after the fallback, but answered before it
+    fetch.mock_once(|_| "once".to_owned());
+
+    assert_eq!(fetch(1), "once");
+    assert_eq!(fetch(2), "default 2");
+    assert_eq!(fetch(3), "default 3");
+}
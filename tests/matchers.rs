@@ -0,0 +1,71 @@
+use mockem::{matchers::*, mock, MockCall};
+
+#[mock]
+fn login(user: &str, attempts: u32, admin: bool) -> Result<String, String> {
+    Ok(format!("{user}:{attempts}:{admin}"))
+}
+
+#[test]
+fn test_mock_matching() {
+    login.mock_matching((pattern("bot_*"), any(), any()), |_, _, _| {
+        Err("bot".to_owned())
+    });
+    login.mock_matching(
+        (any(), ge(3), eq(false)),
+        |_, _, _| Err("locked".to_owned()),
+    );
+    login.mock_matching(
+        (
+            all_of((starts_with("a"), not(ends_with("z")))),
+            any(),
+            any(),
+        ),
+        |user, _, _| Ok(format!("mocked {user}")),
+    );
+
+    assert_eq!(login("bot_1", 0, false), Err("bot".to_owned()));
+    assert_eq!(login("jack", 3, false), Err("locked".to_owned()));
+    assert_eq!(login("jack", 3, true), Ok("jack:3:true".to_owned()));
+    assert_eq!(login("amy", 0, false), Ok("mocked amy".to_owned()));
+    assert_eq!(login("abz", 0, false), Ok("abz:0:false".to_owned()));
+}
+
+#[test]
+fn test_matcher_descriptions() {
+    let matcher = (
+        any_of((eq("root"), contains("admin"))),
+        lt(3),
+        predicate("be set", |b: &bool| *b),
+    );
+
+    assert!(ArgsMatcher::<(&str, u32, bool)>::matches(
+        &matcher,
+        (&"root", &1, &true)
+    ));
+    assert_eq!(
+        ArgsMatcher::<(&str, u32, bool)>::mismatch(&matcher, (&"user", &1, &true)).unwrap(),
+        "expected arg #1 to equal \"root\" or to contain \"admin\" but got \"user\""
+    );
+    assert_eq!(
+        ArgsMatcher::<(&str, u32, bool)>::mismatch(&matcher, (&"admin", &3, &true)).unwrap(),
+        "expected arg #2 to be less than 3 but got 3"
+    );
+    assert_eq!(
+        ArgsMatcher::<(&str, u32, bool)>::mismatch(&matcher, (&"admin", &1, &false)).unwrap(),
+        "expected arg #3 to be set"
+    );
+    assert_eq!(
+        ArgsMatcher::<(&str, u32, bool)>::describe(&matcher),
+        "(to equal \"root\" or to contain \"admin\", to be less than 3, to be set)"
+    );
+
+    assert!(Matcher::<&str>::matches(
+        &pattern("user_?@*.com"),
+        &"user_1@mail.com"
+    ));
+    assert!(!Matcher::<&str>::matches(
+        &pattern("user_?@*.com"),
+        &"user_12@mail.com"
+    ));
+    assert!(Matcher::<String>::matches(&pattern("*"), &String::new()));
+}
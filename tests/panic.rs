@@ -0,0 +1,41 @@
+use std::panic::catch_unwind;
+
+use mockem::{mock, MockCall, Spy};
+
+#[mock]
+fn parse(input: &str) -> u32 {
+    input.parse().unwrap()
+}
+
+#[test]
+fn test_panicking_mock_keeps_its_place() {
+    parse.mock_repeat(None, |input| {
+        assert!(!input.is_empty(), "empty input");
+        1
+    });
+    parse.mock_once(|_| 2);
+
+    assert!(catch_unwind(|| parse("")).is_err());
+    // the indefinite mock is still in front
+    assert_eq!(parse("a"), 1);
+    assert_eq!(parse.call_count(), 2);
+
+    mockem::clear_mocks();
+
+    parse.mock_once(|_| panic!("once"));
+    parse.mock_once(|_| 2);
+
+    assert!(catch_unwind(|| parse("")).is_err());
+    assert!(catch_unwind(|| parse("")).is_err());
+}
+
+#[test]
+fn test_consume_on_panic() {
+    let handle = parse.mock_once(|_| panic!("once")).consume_on_panic();
+    parse.mock_once(|_| 2);
+
+    assert!(catch_unwind(|| parse("")).is_err());
+    assert!(handle.is_consumed());
+    assert_eq!(parse(""), 2);
+    assert_eq!(parse("3"), 3);
+}
@@ -0,0 +1,54 @@
+use mockem::{mock, MockCall, Spy};
+
+#[mock]
+fn depth(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        1 + depth(n - 1)
+    }
+}
+
+#[test]
+fn test_closure_calls_itself() {
+    // the closure's nested call skips its own mock, and sees the rest of the queue
+    depth.mock_repeat(None, |n| depth(n) * 10);
+    depth.mock_fallback(|n| n + 100);
+
+    assert_eq!(depth(3), 1030);
+    assert_eq!(depth.call_count(), 2);
+}
+
+#[test]
+fn test_mock_at_depth() {
+    depth.mock_at_depth(2, |_| 50);
+
+    // depth(3) -> depth(2) -> depth(1), which is mocked
+    assert_eq!(depth(3), 52);
+    assert_eq!(depth(1), 1);
+
+    depth.mock_at_depth(0, |n| depth(n) + 1000);
+
+    // the closure's call is at depth 1, and the one it makes at depth 2
+    assert_eq!(depth(3), 1051);
+}
+
+#[mock]
+async fn fetch(id: u32) -> u32 {
+    tokio::task::yield_now().await;
+
+    if id >= 10 {
+        Box::pin(fetch(id / 10)).await + 1
+    } else {
+        id
+    }
+}
+
+#[tokio::test]
+async fn test_mock_at_depth_concurrent() {
+    fetch.mock_at_depth(1, |id| -> u32 { id + 100 });
+
+    // calls awaited side by side are both at depth 0, however their polls interleave
+    assert_eq!(tokio::join!(fetch(1), fetch(2)), (1, 2));
+    assert_eq!(tokio::join!(fetch(12), fetch(3)), (102, 3));
+}
@@ -0,0 +1,67 @@
+use mockem::{matchers::*, mock, ExpectCalls, MockCall, Sequence, Times};
+
+#[mock]
+fn connect(addr: &str) -> bool {
+    !addr.is_empty()
+}
+
+#[mock]
+fn send(msg: &str) -> usize {
+    msg.len()
+}
+
+#[mock]
+fn close() {}
+
+#[test]
+fn test_sequence() {
+    let seq = Sequence::new();
+    connect.mock_once(|_| true).in_sequence(&seq);
+    let _sent = send
+        .expect()
+        .with((contains("hello"),))
+        .times(Times::exactly(2))
+        .in_sequence(&seq)
+        .returning(|_| 0);
+    close.mock_repeat(None, || ()).in_sequence(&seq);
+
+    assert!(connect(""));
+    assert_eq!(send("hello"), 0);
+    // not part of the sequence
+    assert_eq!(send("bye"), 3);
+    assert_eq!(send("hello again"), 0);
+    close();
+
+    seq.verify();
+}
+
+#[test]
+#[should_panic(
+    expected = "`sequence::send` was called before `sequence::connect`\n    \
+    expected order: sequence::connect, sequence::send, sequence::close\n    \
+    actual order: sequence::send"
+)]
+fn test_sequence_out_of_order() {
+    let seq = Sequence::new();
+    connect.mock_once(|_| true).in_sequence(&seq);
+    send.mock_once(|_| 0).in_sequence(&seq);
+    close.mock_once(|| ()).in_sequence(&seq);
+
+    send("hello");
+}
+
+#[test]
+#[should_panic(
+    expected = "sequence is incomplete, `sequence::close` was not called\n    \
+    expected order: sequence::connect, sequence::close\n    \
+    actual order: sequence::connect"
+)]
+fn test_sequence_incomplete() {
+    let seq = Sequence::new();
+    connect.mock_repeat(None, |_| true).in_sequence(&seq);
+    close.mock_once(|| ()).in_sequence(&seq);
+
+    connect("localhost");
+
+    seq.verify();
+}
@@ -0,0 +1,135 @@
+use std::thread;
+
+use mockem::{mock, MockCall, MockShared};
+
+#[mock]
+fn fetch(key: &str) -> String {
+    format!("real {key}")
+}
+
+#[mock]
+async fn load(id: u32) -> u32 {
+    id
+}
+
+#[mock]
+fn count() -> u32 {
+    0
+}
+
+#[mockem::test(shared)]
+fn test_shared() {
+    fetch.mock_shared(Some(2), |key| format!("shared {key}"));
+
+    // calls from any thread in the scope use up the same mock
+    let from_thread = mockem::thread::spawn(|| fetch("a")).join().unwrap();
+    let context = mockem::context::capture();
+    thread::scope(|s| {
+        s.spawn(|| {
+            let _context = context.enter();
+            assert_eq!(fetch("b"), "shared b");
+        });
+    });
+
+    assert_eq!(from_thread, "shared a");
+    assert_eq!(fetch("c"), "real c");
+
+    // mocks of the thread answer first
+    fetch.mock_shared(None, |_| "shared".to_owned());
+    fetch.mock_once(|_| "local".to_owned());
+
+    assert_eq!(fetch("d"), "local");
+    assert_eq!(fetch("e"), "shared");
+}
+
+#[mockem::test(shared)]
+#[tokio::test]
+async fn test_shared_async() {
+    load.mock_shared(None, |id: u32| id * 2);
+
+    // with the `tokio` feature, the test runs in a task scope, which threads must enter
+    let context = mockem::context::capture();
+    let from_blocking = tokio::task::spawn_blocking(move || {
+        let _context = context.enter();
+        block_on(load(2))
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(from_blocking, 4);
+    assert_eq!(load(3).await, 6);
+}
+
+#[test]
+fn test_scope_ends() {
+    let scope = mockem::shared_scope();
+    fetch.mock_shared(None, |_| "shared".to_owned());
+
+    assert_eq!(
+        mockem::thread::spawn(|| fetch("a")).join().unwrap(),
+        "shared"
+    );
+
+    drop(scope);
+
+    assert_eq!(
+        mockem::thread::spawn(|| fetch("a")).join().unwrap(),
+        "real a"
+    );
+}
+
+#[mockem::test(shared)]
+fn test_outside_scope() {
+    fetch.mock_shared(None, |_| "shared".to_owned());
+
+    // threads that didn't enter the scope don't see it
+    assert_eq!(thread::spawn(|| fetch("a")).join().unwrap(), "real a");
+    thread::scope(|s| {
+        s.spawn(|| {
+            let _scope = mockem::shared_scope();
+            assert_eq!(fetch("b"), "real b");
+        });
+    });
+
+    assert_eq!(fetch("c"), "shared");
+}
+
+#[mockem::test(shared)]
+fn test_repeat_zero() {
+    // shared and local mocks repeated 0 times both answer no calls
+    fetch.mock_shared(Some(0), |_| "shared".to_owned());
+    fetch.mock_repeat(Some(0), |_| "local".to_owned());
+
+    assert_eq!(fetch("a"), "real a");
+}
+
+#[mockem::test]
+#[should_panic(expected = "`mock_shared` needs a shared scope")]
+fn test_no_scope() {
+    fetch.mock_shared(None, |_| String::new());
+}
+
+#[mockem::test(shared)]
+#[should_panic(expected = "1 shared mocked call was never made to `shared::fetch`")]
+fn test_unused_shared() {
+    fetch.mock_shared(Some(2), |key| format!("shared {key}"));
+
+    assert_eq!(fetch("a"), "shared a");
+}
+
+#[mockem::test(shared)]
+#[tokio::test(flavor = "multi_thread")]
+#[should_panic(expected = "2 shared mocked calls were never made to `shared::load`")]
+async fn test_unused_shared_async() {
+    load.mock_shared(Some(2), |id: u32| id * 2);
+    // indefinite mocks are never reported
+    count.mock_shared(None, || 1);
+}
+
+/// Run a future on the current thread.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
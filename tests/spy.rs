@@ -0,0 +1,64 @@
+use mockem::{mock, MockCall, Spy};
+
+#[mock]
+fn greet(name: &str, times: usize) -> String {
+    name.repeat(times)
+}
+
+struct NoDebug;
+
+#[mock]
+fn opaque(_no_debug: NoDebug, n: u8) -> u8 {
+    n
+}
+
+#[mock]
+async fn fetch(id: u32) -> String {
+    format!("item {id}")
+}
+
+#[test]
+fn test_calls() {
+    greet.mock_once(|_, _| "mocked".to_owned());
+
+    assert_eq!(greet("a", 2), "mocked");
+    assert_eq!(greet("b", 3), "bbb");
+
+    let calls = greet.calls();
+    assert_eq!(greet.call_count(), 2);
+    assert_eq!(
+        calls[0].args(),
+        [Some("\"a\"".to_owned()), Some("2".to_owned())]
+    );
+    assert!(calls[0].is_mocked());
+    assert!(!calls[1].is_mocked());
+
+    let last = greet.last_call().unwrap();
+    assert_eq!(last.arg(0), Some("\"b\""));
+    assert!(last.to_string().ends_with("greet(\"b\", 3)"));
+
+    greet.clear_calls();
+    assert_eq!(greet.call_count(), 0);
+    assert_eq!(greet.last_call(), None);
+}
+
+#[test]
+fn test_calls_without_debug() {
+    assert_eq!(opaque(NoDebug, 7), 7);
+
+    let call = opaque.last_call().unwrap();
+    assert_eq!(call.arg(0), None);
+    assert_eq!(call.arg(1), Some("7"));
+    assert!(call.to_string().ends_with("opaque(_, 7)"));
+}
+
+#[tokio::test]
+async fn test_async_calls() {
+    fetch.mock_once(|id| format!("mocked {id}"));
+
+    assert_eq!(fetch(1).await, "mocked 1");
+    assert_eq!(fetch(2).await, "item 2");
+
+    assert_eq!(fetch.call_count(), 2);
+    assert_eq!(fetch.last_call().unwrap().arg(0), Some("2"));
+}
@@ -0,0 +1,35 @@
+use mockem::{mock, AllowReal, MockCall};
+
+#[mock]
+fn fetch(url: &str) -> String {
+    format!("fetched {url}")
+}
+
+#[mock]
+fn now() -> u64 {
+    42
+}
+
+#[test]
+#[should_panic(expected = "`strict::fetch` ran its real implementation in strict mode")]
+fn test_strict() {
+    let fetched = mockem::strict(|| {
+        fetch.mock_once(|_| "mocked".to_owned());
+
+        fetch("a")
+    });
+    assert_eq!(fetched, "mocked");
+    // strict mode ends with the closure
+    assert_eq!(fetch("b"), "fetched b");
+
+    mockem::strict(|| fetch("c"));
+}
+
+#[mockem::test(strict)]
+fn test_strict_allowed() {
+    now.allow_real();
+    fetch.mock_repeat(None, |url| url.to_owned());
+
+    assert_eq!(now(), 42);
+    assert_eq!(fetch("a"), "a");
+}
@@ -0,0 +1,37 @@
+use mockem::{mock, MockCall};
+
+#[mock]
+fn foo() -> String {
+    "foo".to_owned()
+}
+
+#[mock]
+async fn bar() -> String {
+    "bar".to_owned()
+}
+
+#[mockem::test]
+fn test_isolated() {
+    foo.mock_once(|| "used".to_owned());
+    // indefinite mocks don't need to be used
+    foo.mock_repeat(None, || "left over".to_owned());
+
+    assert_eq!(foo(), "used");
+}
+
+#[mockem::test]
+#[tokio::test]
+async fn test_isolated_async() {
+    bar.mock_once(|| "mocked".to_owned());
+
+    assert_eq!(bar().await, "mocked");
+}
+
+#[mockem::test]
+#[should_panic(expected = "3 mocked calls were never made to `test_attr::foo`")]
+fn test_unused_mocks() {
+    foo.mock_repeat(None, || "indefinite".to_owned());
+    foo.mock_repeat(Some(3), || "mocked".to_owned());
+
+    foo();
+}
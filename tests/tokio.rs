@@ -0,0 +1,111 @@
+#![cfg(feature = "tokio")]
+
+use std::{
+    future::Future,
+    sync::Arc,
+    task::{Context, Wake, Waker},
+};
+
+use mockem::{mock, MockCall, MockShared};
+
+#[mock]
+async fn fetch(key: &str) -> String {
+    format!("real {key}")
+}
+
+#[mock]
+fn now() -> u64 {
+    0
+}
+
+#[mockem::test(shared)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_task_scope() {
+    fetch.mock_shared(None, |key: &str| format!("shared {key}"));
+
+    for _ in 0..10 {
+        tokio::task::yield_now().await;
+        assert_eq!(fetch("a").await, "shared a");
+    }
+
+    let child = mockem::tokio::spawn(async { fetch("b").await });
+    let blocking =
+        mockem::tokio::spawn_blocking(|| tokio::runtime::Handle::current().block_on(fetch("c")));
+    // plain tasks aren't in the scope
+    let plain = tokio::spawn(async { fetch("d").await });
+
+    assert_eq!(child.await.unwrap(), "shared b");
+    assert_eq!(blocking.await.unwrap(), "shared c");
+    assert_eq!(plain.await.unwrap(), "real d");
+}
+
+#[mockem::test]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_local_mocks_in_tasks() {
+    fetch.mock_repeat(None, |key: &str| format!("local {key}"));
+
+    assert_eq!(fetch("a").await, "local a");
+    // local mocks stay on the test thread, the task panics rather than calling the real fn
+    let child = mockem::tokio::spawn(async { fetch("b").await });
+    assert!(child.await.unwrap_err().is_panic());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_parallel_scopes() {
+    let scoped = |value: &'static str| {
+        mockem::tokio::scope(async move {
+            fetch.mock_shared(None, move |_: &str| value.to_owned());
+
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+                assert_eq!(fetch("a").await, value);
+            }
+        })
+    };
+
+    let (a, b) = tokio::join!(
+        mockem::tokio::spawn(scoped("a")),
+        mockem::tokio::spawn(scoped("b"))
+    );
+    a.unwrap();
+    b.unwrap();
+
+    assert_eq!(fetch("a").await, "real a");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_local_mocks_on_another_worker() {
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let mut task = Box::pin(mockem::tokio::scope(async {
+        now.mock_repeat(Some(2), || 1);
+        assert_eq!(now(), 1);
+        tokio::task::yield_now().await;
+        now()
+    }));
+
+    // the mock is queued on the test thread, and follows the task to a worker thread
+    let waker = Waker::from(Arc::new(NoopWaker));
+    assert!(task
+        .as_mut()
+        .poll(&mut Context::from_waker(&waker))
+        .is_pending());
+    assert_eq!(now(), 0);
+
+    assert_eq!(tokio::spawn(task).await.unwrap(), 1);
+    assert_eq!(now(), 0);
+}
+
+#[mockem::test(shared)]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[should_panic(expected = "1 mocked call was never made to `tokio::now`")]
+async fn test_unused_local_mocks_in_task_scope() {
+    now.mock_repeat(Some(2), || 1);
+
+    tokio::task::yield_now().await;
+    assert_eq!(now(), 1);
+}
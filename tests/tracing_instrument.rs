@@ -14,7 +14,7 @@ trait Bar {
 impl Bar for Foo {
     #[instrument(skip(self))]
     async fn bar(&self, b: &str) -> String {
-        format!("bar")
+        "bar".to_owned()
     }
 }
 
@@ -0,0 +1,43 @@
+use mockem::{mock, MockCall, MockWhen};
+
+#[mock]
+fn get(key: &str, version: u32) -> String {
+    format!("real {key}@{version}")
+}
+
+struct Db;
+
+#[mock]
+impl Db {
+    fn load(&self, id: u32) -> Option<String> {
+        Some(format!("row {id}"))
+    }
+}
+
+#[test]
+fn test_mock_when() {
+    get.mock_when(|key, _| *key == "user", |_, v| format!("user@{v}"));
+    get.mock_when(
+        |key, v| *key == "admin" && *v > 1,
+        |_, _| "new admin".to_owned(),
+    );
+
+    assert_eq!(get("user", 1), "user@1");
+    assert_eq!(get("admin", 2), "new admin");
+    // matches nothing, so falls through to the real function
+    assert_eq!(get("admin", 1), "real admin@1");
+    assert_eq!(get("user", 3), "user@3");
+}
+
+#[test]
+fn test_mock_when_falls_through_to_next_mock() {
+    let handle = Db::load.mock_when(|_, id| *id == 0, |_, _| None);
+    Db::load.mock_once(|_, id| Some(format!("mocked {id}")));
+
+    assert_eq!(Db.load(0), None);
+    assert_eq!(Db.load(1).as_deref(), Some("mocked 1"));
+    assert_eq!(Db.load(2).as_deref(), Some("row 2"));
+
+    handle.cancel();
+    assert_eq!(Db.load(0).as_deref(), Some("row 0"));
+}
@@ -0,0 +1,56 @@
+use mockem::{mock, MockCall, MockWrap, Spy};
+
+#[mock]
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    Ok(path.as_bytes().to_vec())
+}
+
+struct Counter(u32);
+
+#[mock]
+impl Counter {
+    fn next(&self, step: u32) -> u32 {
+        self.0 + step
+    }
+}
+
+#[test]
+fn test_mock_wrap() {
+    let handle = read.mock_wrap(|original, path| {
+        original(path).map(|mut bytes| {
+            bytes[1] = 0;
+            bytes
+        })
+    });
+    read.mock_once(|_| Err("queued after the wrap".to_owned()));
+
+    assert_eq!(read("abc"), Ok(vec![b'a', 0, b'c']));
+    assert_eq!(read.call_count(), 1);
+    assert!(read.last_call().unwrap().is_mocked());
+
+    handle.cancel();
+    assert_eq!(read("abc"), Err("queued after the wrap".to_owned()));
+}
+
+#[test]
+fn test_mock_wrap_method() {
+    Counter::next.mock_wrap(|original, counter, step| original(counter, step) * 10);
+
+    assert_eq!(Counter(1).next(2), 30);
+
+    mockem::strict(|| assert_eq!(Counter(0).next(1), 10));
+}
+
+#[test]
+fn test_mock_wrap_clearing_mocks() {
+    read.mock_wrap(|original, path| {
+        mockem::clear_mocks();
+        original(path)
+    });
+
+    assert_eq!(read("abc"), Ok(b"abc".to_vec()));
+
+    // the mock cleared before calling the original doesn't leave later calls bypassing their mocks
+    read.mock_once(|_| Err("mocked".to_owned()));
+    assert_eq!(read("abc"), Err("mocked".to_owned()));
+}